@@ -39,9 +39,74 @@ fn ff1_binary_benchmark(c: &mut Criterion<CyclesPerByte>) {
     binary_ff1_group.finish();
 }
 
+fn ff1_decimal_benchmark(c: &mut Criterion<CyclesPerByte>) {
+    let numerals: Vec<u16> = (0..19).map(|i| i % 10).collect();
+
+    let fpe_ff = fpe::ff1::FF1::<Aes256>::new(&[0; 32], 10).unwrap();
+    let mut fpe_group = c.benchmark_group("fpe-decimal");
+    for size in [9, 16, 19].iter() {
+        fpe_group.throughput(Throughput::Elements(*size as u64));
+        fpe_group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            b.iter(|| {
+                fpe_ff.encrypt(
+                    &[],
+                    &fpe::ff1::FlexibleNumeralString::from(numerals[..size].to_vec()),
+                )
+            });
+        });
+    }
+    fpe_group.finish();
+
+    let mut prepared_group = c.benchmark_group("fpe-decimal-prepared");
+    for size in [9, 16, 19].iter() {
+        let ctx = fpe_ff.prepare(*size, &[]).unwrap();
+        prepared_group.throughput(Throughput::Elements(*size as u64));
+        prepared_group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            b.iter(|| {
+                ctx.encrypt(&fpe::ff1::FlexibleNumeralString::from(
+                    numerals[..size].to_vec(),
+                ))
+            });
+        });
+    }
+    prepared_group.finish();
+
+    let mut batch_group = c.benchmark_group("fpe-decimal-batch");
+    for size in [9, 16, 19].iter() {
+        let xs: Vec<_> = (0..64)
+            .map(|_| fpe::ff1::FlexibleNumeralString::from(numerals[..*size].to_vec()))
+            .collect();
+        batch_group.throughput(Throughput::Elements((*size * xs.len()) as u64));
+        batch_group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, _| {
+            b.iter(|| fpe_ff.encrypt_batch(fpe::ff1::Tweaks::Shared(&[]), &xs));
+        });
+    }
+    batch_group.finish();
+}
+
+fn ff1_conversion_benchmark(c: &mut Criterion<CyclesPerByte>) {
+    use fpe::ff1::{FlexibleNumeralString, NumeralString};
+
+    for &radix in [10, 36].iter() {
+        let mut group = c.benchmark_group(format!("fpe-conversion-{}", radix));
+        for size in [10usize, 100, 1000, 10000].iter() {
+            let ns = FlexibleNumeralString::from(
+                (0..*size)
+                    .map(|i| (i as u32 % radix) as u16)
+                    .collect::<Vec<_>>(),
+            );
+            group.throughput(Throughput::Elements(*size as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+                b.iter(|| FlexibleNumeralString::str_radix(ns.num_radix(radix), radix, size));
+            });
+        }
+        group.finish();
+    }
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default().with_measurement(CyclesPerByte);
-    targets = ff1_binary_benchmark
+    targets = ff1_binary_benchmark, ff1_decimal_benchmark, ff1_conversion_benchmark
 );
 criterion_main!(benches);
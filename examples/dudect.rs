@@ -0,0 +1,157 @@
+//! A dudect-style timing test of the FF1 arithmetic backends.
+//!
+//! Following "Dude, is my code constant time?" (Reparaz, Balasch and Verbauwhede), this
+//! measures the time to encrypt numeral strings of two classes: a fixed numeral string
+//! of zeros, and random numeral strings. The classes are interleaved randomly, and
+//! Welch's t-test compares their timing distributions, both in full and cropped at
+//! several percentiles to remove the noise of interrupts and context switches. A |t|
+//! above 4.5 is strong evidence that the timing depends on the numerals.
+//!
+//! Run it on an otherwise idle machine with:
+//!
+//! ```text
+//! cargo run --release --features constant-time --example dudect [samples]
+//! ```
+
+use aes::Aes256;
+use fpe::ff1::{ConstantTimeNumeralString, FlexibleNumeralString, NumeralString, FF1};
+use std::env;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The threshold above which |t| indicates a timing leak.
+const THRESHOLD: f64 = 4.5;
+
+/// The percentiles at which the measurements are cropped, in addition to the full set.
+const PERCENTILES: [f64; 4] = [0.5, 0.75, 0.9, 0.99];
+
+/// A xorshift64* generator, which is good enough to pick classes and numerals.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Running mean and variance of one class, with Welford's algorithm.
+#[derive(Default)]
+struct Stats {
+    n: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Stats {
+    fn push(&mut self, x: f64) {
+        self.n += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.n;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        self.m2 / (self.n - 1.0)
+    }
+}
+
+/// Returns Welch's t statistic for the two classes.
+fn welch_t(a: &Stats, b: &Stats) -> f64 {
+    (a.mean - b.mean) / (a.variance() / a.n + b.variance() / b.n).sqrt()
+}
+
+/// Returns the largest |t| over the full measurements and their cropped subsets.
+fn max_t(measurements: &[(usize, u64)]) -> f64 {
+    let mut sorted: Vec<u64> = measurements.iter().map(|&(_, t)| t).collect();
+    sorted.sort_unstable();
+    let cutoffs = PERCENTILES
+        .iter()
+        .map(|p| sorted[(p * sorted.len() as f64) as usize])
+        .chain(Some(u64::max_value()));
+
+    cutoffs
+        .map(|cutoff| {
+            let mut classes = [Stats::default(), Stats::default()];
+            for &(class, t) in measurements.iter().filter(|&&(_, t)| t <= cutoff) {
+                classes[class].push(t as f64);
+            }
+            welch_t(&classes[0], &classes[1]).abs()
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Measures the encryption time of the two classes of numeral strings of type NS.
+fn measure<NS: NumeralString + From<Vec<u16>>>(
+    ff: &FF1<Aes256>,
+    radix: u32,
+    len: usize,
+    samples: usize,
+    rng: &mut Rng,
+) -> f64 {
+    // The inputs are prepared in advance, so that only the encryption is timed.
+    let inputs: Vec<(usize, NS)> = (0..samples)
+        .map(|_| {
+            let class = (rng.next() & 1) as usize;
+            let numerals = (0..len)
+                .map(|_| match class {
+                    0 => 0,
+                    _ => (rng.next() % u64::from(radix)) as u16,
+                })
+                .collect::<Vec<_>>();
+            (class, NS::from(numerals))
+        })
+        .collect();
+
+    // Warm up the caches and the branch predictors.
+    for (_, x) in inputs.iter().take(samples / 100) {
+        ff.encrypt(b"dudect", x).unwrap();
+    }
+
+    let measurements: Vec<(usize, u64)> = inputs
+        .iter()
+        .map(|(class, x)| {
+            let start = Instant::now();
+            let ct = ff.encrypt(b"dudect", x);
+            let elapsed = start.elapsed().as_nanos() as u64;
+            assert!(ct.is_ok());
+            (*class, elapsed)
+        })
+        .collect();
+    max_t(&measurements)
+}
+
+fn main() {
+    let samples = env::args()
+        .nth(1)
+        .map(|s| s.parse().expect("samples must be a number"))
+        .unwrap_or(200_000);
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let mut rng = Rng(seed | 1);
+
+    let radix = 10;
+    let ff = FF1::<Aes256>::new(&[0x42; 32], radix).unwrap();
+
+    println!("{} samples per test, leak if |t| > {}", samples, THRESHOLD);
+    for &len in [16, 40, 100].iter() {
+        for &(name, constant_time) in [("flexible", false), ("constant-time", true)].iter() {
+            let t = if constant_time {
+                measure::<ConstantTimeNumeralString>(&ff, radix, len, samples, &mut rng)
+            } else {
+                measure::<FlexibleNumeralString>(&ff, radix, len, samples, &mut rng)
+            };
+            println!(
+                "radix {} length {:>3} {:>13}: max |t| = {:>7.2} {}",
+                radix,
+                len,
+                name,
+                t,
+                if t > THRESHOLD { "LEAK" } else { "ok" }
+            );
+        }
+    }
+}
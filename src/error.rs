@@ -0,0 +1,100 @@
+//! The error type for format-preserving encryption operations.
+
+use core::fmt;
+
+/// Errors that can occur when creating a cipher, or when encrypting or decrypting with
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The radix is not supported by the algorithm.
+    InvalidRadix(u32),
+    /// The key does not have the length that the block cipher requires.
+    InvalidKeyLength {
+        /// The key length that the block cipher requires, in bytes.
+        expected: usize,
+        /// The length of the given key, in bytes.
+        actual: usize,
+    },
+    /// A numeral is not valid for the radix.
+    InvalidNumeral {
+        /// The position of the numeral in the numeral string.
+        position: usize,
+        /// The value of the numeral.
+        value: u32,
+        /// The radix of the numeral string.
+        radix: u32,
+    },
+    /// The length of the input is outside the bounds that the algorithm supports.
+    LengthOutOfBounds {
+        /// The length of the input.
+        len: usize,
+        /// The minimum supported length.
+        min: usize,
+        /// The maximum supported length.
+        max: usize,
+    },
+    /// The numeral string is longer than its numeric type can represent in the radix.
+    NumeralStringTooLong {
+        /// The length of the numeral string.
+        len: usize,
+        /// The maximum length that the numeric type supports.
+        max: usize,
+    },
+    /// The tweak is longer than the algorithm allows.
+    TweakTooLong {
+        /// The length of the given tweak, in bytes.
+        len: usize,
+        /// The maximum tweak length, in bytes.
+        max: usize,
+    },
+    /// The domain of the input is too small for the algorithm to be secure, or empty.
+    DomainTooSmall,
+    /// An integer is outside the domain, or the domain does not fit the integer type.
+    ValueOutOfRange,
+    /// The regular expression could not be compiled.
+    InvalidPattern,
+    /// The input does not match the format.
+    NoMatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidRadix(radix) => write!(f, "radix {} is not supported", radix),
+            Error::InvalidKeyLength { expected, actual } => write!(
+                f,
+                "key length {} is invalid, expected {} bytes",
+                actual, expected
+            ),
+            Error::InvalidNumeral {
+                position,
+                value,
+                radix,
+            } => write!(
+                f,
+                "numeral {} at position {} is invalid for radix {}",
+                value, position, radix
+            ),
+            Error::LengthOutOfBounds { len, min, max } => {
+                write!(f, "length {} is not in [{}..{}]", len, min, max)
+            }
+            Error::NumeralStringTooLong { len, max } => write!(
+                f,
+                "numeral string length {} exceeds the maximum of {} for its numeric type",
+                len, max
+            ),
+            Error::TweakTooLong { len, max } => write!(
+                f,
+                "tweak length {} exceeds the maximum of {} bytes",
+                len, max
+            ),
+            Error::DomainTooSmall => write!(f, "the domain is too small"),
+            Error::ValueOutOfRange => write!(f, "the value is out of range"),
+            Error::InvalidPattern => write!(f, "the regular expression is invalid"),
+            Error::NoMatch => write!(f, "the input does not match the format"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
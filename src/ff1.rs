@@ -1,26 +1,118 @@
 //! A Rust implementation of the FF1 algorithm, specified in
 //! [NIST Special Publication 800-38G](http://dx.doi.org/10.6028/NIST.SP.800-38G).
-
-use aes::block_cipher::{generic_array::GenericArray, Block, BlockCipher, NewBlockCipher};
+//!
+//! # Supported block ciphers
+//!
+//! FF1 is defined for block ciphers with a 128-bit block size, and this is enforced at
+//! compile time. The following ciphers are supported:
+//!
+//! - AES-128 (`aes::Aes128`), tested against the NIST FF1 samples
+//! - AES-192 (`aes::Aes192`), tested against the NIST FF1 samples
+//! - AES-256 (`aes::Aes256`), tested against the NIST FF1 samples
+//! - SM4 (`sm4::Sm4`), tested against vectors from a separate implementation
+//!
+//! The type system accepts any other implementation of the `block-cipher` traits with a
+//! 128-bit block, but FF1 is not tested with any of them. In particular, Camellia and
+//! ARIA are not supported, as their RustCrypto implementations require newer `cipher`
+//! traits than this crate uses.
+//!
+//! # Arithmetic backends
+//!
+//! The numeral string type selects the integer arithmetic that FF1 uses.
+//! `ArrayNumeralString` uses native integers, while `FlexibleNumeralString` and
+//! `BinaryNumeralString` switch to `num-bigint` for long numeral strings. The time that
+//! these take depends on the numerals being encrypted.
+//!
+//! With the `constant-time` feature, `ConstantTimeNumeralString` uses fixed-width
+//! integers whose size is chosen from radix^n, and whose operations take time that is
+//! independent of the numerals. It produces the same ciphertexts as the other numeral
+//! strings, and should be used when an attacker can measure the timing of encryption,
+//! for example from another tenant of the same host.
+
+use aes::block_cipher::{
+    generic_array::{
+        typenum::{Unsigned, U16},
+        GenericArray,
+    },
+    Block, BlockCipher, Key, NewBlockCipher,
+};
 use block_modes::{block_padding::NoPadding, BlockMode, Cbc};
-use std::cmp;
+use core::cmp;
+
+use crate::ffx::{self, Addition, Feistel, Halves};
+use crate::Error;
 
+#[cfg(feature = "alloc")]
 mod alloc;
-pub use alloc::{BinaryNumeralString, FlexibleNumeralString};
+#[cfg(feature = "alloc")]
+pub use self::alloc::{
+    AutoBytes, AutoNumeral, BinaryNumeralString, BitOrder, FlexibleNumeralString,
+};
+
+#[cfg(feature = "alloc")]
+mod batch;
+#[cfg(feature = "alloc")]
+pub use self::batch::Tweaks;
+
+#[cfg(feature = "constant-time")]
+mod constant_time;
+#[cfg(feature = "constant-time")]
+pub use self::constant_time::{ConstantTimeNumeral, ConstantTimeNumeralString};
+
+#[cfg(feature = "parallel")]
+mod parallel;
+
+mod in_place;
+mod native;
+pub use native::{ArrayNumeralString, NativeBytes};
+
+/// The minimum domain size radix^minlen required by NIST SP 800-38G Revision 1.
+pub(crate) const MIN_DOMAIN: u64 = 1_000_000;
+
+/// The largest length and tweak length that the four-byte fields of P can encode.
+const MAX_LEN: usize = u32::max_value() as usize;
+
+/// The number of fractional bits of log2_fixed.
+const LOG2_FRAC_BITS: u32 = 62;
+
+/// Returns log2(radix) * 2^62, rounded down.
+///
+/// This is computed with integer arithmetic, one fractional bit at a time, so that it
+/// does not depend on a floating-point library. It is more precise than f64::log2.
+fn log2_fixed(radix: u32) -> u128 {
+    let int = 31 - radix.leading_zeros();
+    let one = 1u128 << LOG2_FRAC_BITS;
+
+    // x = radix / 2^int in [1, 2), with 62 fractional bits.
+    let mut x = (u128::from(radix) << LOG2_FRAC_BITS) >> int;
+    let mut res = u128::from(int);
+    for _ in 0..LOG2_FRAC_BITS {
+        // log2(x^2) = 2 * log2(x), so squaring x shifts the next bit into the integer
+        // part of its logarithm.
+        x = (x * x) >> LOG2_FRAC_BITS;
+        res <<= 1;
+        if x >= 2 * one {
+            x >>= 1;
+            res |= 1;
+        }
+    }
+    res
+}
 
-#[derive(Debug, PartialEq)]
-enum Radix {
-    /// A radix in [2..2^16]. It uses floating-point arithmetic.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Radix {
+    /// A radix in [2..2^16]. Byte lengths are computed from a fixed-point
+    /// approximation of log2(radix).
     Any(u32),
-    /// A radix 2^i for i in [1..16]. It does not use floating-point arithmetic.
+    /// A radix 2^i for i in [1..16]. Byte lengths are computed exactly from i.
     PowerTwo { radix: u32, log_radix: u8 },
 }
 
 impl Radix {
-    pub fn from(radix: u32) -> Result<Self, ()> {
+    pub fn from(radix: u32) -> Result<Self, Error> {
         // radix must be in range [2..2^16]
         if radix < 2 || radix > (1 << 16) {
-            return Err(());
+            return Err(Error::InvalidRadix(radix));
         }
 
         let mut tmp = radix;
@@ -47,14 +139,30 @@ impl Radix {
     }
 
     /// Calculates b = ceil(ceil(v * log2(radix)) / 8).
-    fn calculate_b(&self, v: usize) -> usize {
+    pub(crate) fn calculate_b(&self, v: usize) -> usize {
         match *self {
-            Radix::Any(r) => (v as f64 * f64::from(r).log2() / 8f64).ceil() as usize,
+            Radix::Any(r) => {
+                let bits = v as u128 * log2_fixed(r);
+                let div = 8 << LOG2_FRAC_BITS;
+                ((bits + div - 1) / div) as usize
+            }
             Radix::PowerTwo { log_radix, .. } => ((v * log_radix as usize) + 7) / 8,
         }
     }
 
-    fn to_u32(&self) -> u32 {
+    /// Returns minlen, the smallest length for which radix^minlen >= 1,000,000.
+    pub(crate) fn min_len(&self) -> usize {
+        let radix = u64::from(self.to_u32());
+        let mut len = 0;
+        let mut domain = 1;
+        while domain < MIN_DOMAIN {
+            domain *= radix;
+            len += 1;
+        }
+        len
+    }
+
+    pub(crate) fn to_u32(&self) -> u32 {
         match *self {
             Radix::Any(r) => r,
             Radix::PowerTwo { radix, .. } => radix,
@@ -63,7 +171,7 @@ impl Radix {
 }
 
 /// An integer.
-pub trait Numeral {
+pub trait Numeral: Sized {
     /// Type used for byte representations.
     type Bytes: AsRef<[u8]>;
 
@@ -73,11 +181,24 @@ pub trait Numeral {
     /// Returns the big-endian byte representation of this integer.
     fn to_bytes(&self, b: usize) -> Self::Bytes;
 
+    /// Returns radix^m.
+    fn exp(radix: u32, m: usize) -> Self;
+
+    /// Compute (self + other) mod modulus
+    fn add_mod(self, other: Self, modulus: &Self) -> Self;
+
+    /// Compute (self - other) mod modulus
+    fn sub_mod(self, other: Self, modulus: &Self) -> Self;
+
     /// Compute (self + other) mod radix^m
-    fn add_mod_exp(self, other: Self, radix: u32, m: usize) -> Self;
+    fn add_mod_exp(self, other: Self, radix: u32, m: usize) -> Self {
+        self.add_mod(other, &Self::exp(radix, m))
+    }
 
     /// Compute (self - other) mod radix^m
-    fn sub_mod_exp(self, other: Self, radix: u32, m: usize) -> Self;
+    fn sub_mod_exp(self, other: Self, radix: u32, m: usize) -> Self {
+        self.sub_mod(other, &Self::exp(radix, m))
+    }
 }
 
 /// For a given base, a finite, ordered sequence of numerals for the base.
@@ -88,15 +209,69 @@ pub trait NumeralString: Sized {
     /// Returns whether this numeral string is valid for the base radix.
     fn is_valid(&self, radix: u32) -> bool;
 
+    /// Returns the position and value of the first numeral in this numeral string that
+    /// is not valid for the base radix, if any.
+    ///
+    /// The default implementation locates the first invalid numeral by bisecting with
+    /// `split` and `is_valid`, which is only done if the numeral string is not valid.
+    /// Implementations should override it if they can do better.
+    fn invalid_numeral(&self, radix: u32) -> Option<(usize, u32)> {
+        if self.is_valid(radix) {
+            return None;
+        }
+
+        // The prefix of length lo is valid, and the prefix of length hi is not.
+        let (mut lo, mut hi) = (0, self.len());
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.split(mid).0.is_valid(radix) {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // A single numeral represents its own value in any radix.
+        let numeral = self.split(lo).1.split(1).0.num_radix(radix);
+        let value = numeral
+            .to_bytes(4)
+            .as_ref()
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | u32::from(*b));
+        Some((lo, value))
+    }
+
     /// Returns the number of numerals in this numeral string.
     fn len(&self) -> usize;
 
+    /// Returns the maximum length of numeral strings of this type that can be
+    /// encrypted in the base radix, which is bounded by the integers that `Num` can
+    /// represent.
+    fn max_len(_radix: u32) -> usize {
+        usize::max_value()
+    }
+
     /// Splits this numeral string into two sections X[..u] and X[u..].
     fn split(&self, u: usize) -> (Self, Self);
 
     /// Concatenates two numeral strings.
     fn concat(a: Self, b: Self) -> Self;
 
+    /// Returns the numerals of this numeral string in reverse order.
+    ///
+    /// The default implementation reverses the numerals one at a time with `split` and
+    /// `concat`, which takes quadratic time. Implementations should override it if they
+    /// can do better.
+    fn rev(&self) -> Self {
+        let (mut res, mut rest) = self.split(0);
+        while rest.len() > 0 {
+            let (first, tail) = rest.split(1);
+            res = Self::concat(first, res);
+            rest = tail;
+        }
+        res
+    }
+
     /// The number that this numeral string represents in the base radix
     /// when the numerals are valued in decreasing order of significance
     /// (big-endian order).
@@ -108,16 +283,55 @@ pub trait NumeralString: Sized {
     fn str_radix(x: Self::Num, radix: u32, m: usize) -> Self;
 }
 
+/// Checks that every numeral of the given numeral string is valid for the base radix,
+/// and that the numeral string is short enough for its numeric type.
+pub(crate) fn check_numerals<NS: NumeralString>(x: &NS, radix: u32) -> Result<(), Error> {
+    let max = NS::max_len(radix);
+    if x.len() > max {
+        return Err(Error::NumeralStringTooLong { len: x.len(), max });
+    }
+
+    match x.invalid_numeral(radix) {
+        Some((position, value)) => Err(Error::InvalidNumeral {
+            position,
+            value,
+            radix,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the given key as a key for the block cipher.
+///
+/// Returns an error if the key does not have the length that the block cipher requires.
+pub(crate) fn key_from_slice<CIPH: NewBlockCipher>(key: &[u8]) -> Result<&Key<CIPH>, Error> {
+    let expected = CIPH::KeySize::to_usize();
+    if key.len() != expected {
+        return Err(Error::InvalidKeyLength {
+            expected,
+            actual: key.len(),
+        });
+    }
+    Ok(GenericArray::from_slice(key))
+}
+
+/// Creates a block cipher with the given key.
+///
+/// Returns an error if the key does not have the length that the block cipher requires.
+pub(crate) fn new_cipher<CIPH: NewBlockCipher>(key: &[u8]) -> Result<CIPH, Error> {
+    Ok(CIPH::new(key_from_slice::<CIPH>(key)?))
+}
+
 #[derive(Clone)]
-struct Prf<CIPH: NewBlockCipher + BlockCipher> {
+pub(crate) struct Prf<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> {
     state: Cbc<CIPH, NoPadding>,
     // Contains the output when offset = 0, and partial input otherwise
     buf: [Block<CIPH>; 1],
     offset: usize,
 }
 
-impl<CIPH: NewBlockCipher + BlockCipher + Clone> Prf<CIPH> {
-    fn new(ciph: &CIPH) -> Self {
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> Prf<CIPH> {
+    pub(crate) fn new(ciph: &CIPH) -> Self {
         let ciph = ciph.clone();
         Prf {
             state: Cbc::new(ciph, GenericArray::from_slice(&[0; 16])),
@@ -126,7 +340,7 @@ impl<CIPH: NewBlockCipher + BlockCipher + Clone> Prf<CIPH> {
         }
     }
 
-    fn update(&mut self, mut data: &[u8]) {
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
         while !data.is_empty() {
             let to_read = cmp::min(self.buf[0].len() - self.offset, data.len());
             self.buf[0][self.offset..self.offset + to_read].copy_from_slice(&data[..to_read]);
@@ -143,13 +357,13 @@ impl<CIPH: NewBlockCipher + BlockCipher + Clone> Prf<CIPH> {
     /// Returns the current PRF output.
     ///
     /// The caller MUST ensure that the PRF has processed an integer number of blocks.
-    fn output(&self) -> &Block<CIPH> {
+    pub(crate) fn output(&self) -> &Block<CIPH> {
         assert_eq!(self.offset, 0);
         &self.buf[0]
     }
 }
 
-fn generate_s<'a, CIPH: BlockCipher>(
+fn generate_s<'a, CIPH: BlockCipher<BlockSize = U16>>(
     ciph: &'a CIPH,
     r: &'a Block<CIPH>,
     d: usize,
@@ -167,40 +381,200 @@ fn generate_s<'a, CIPH: BlockCipher>(
         .take(d)
 }
 
+/// Returns (-t-b-1) mod 16, the number of zero bytes between T and [i] in Q.
+fn q_padding(t: usize, b: usize) -> usize {
+    (((-(t as i32) - (b as i32) - 1) % 16) + 16) as usize % 16
+}
+
+/// The policy for the minimum length of numeral strings that FF1 accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainPolicy {
+    /// Require radix^minlen >= 1,000,000, as specified in NIST SP 800-38G Revision 1.
+    Strict,
+    /// Only require a length of at least 2, as in the original NIST SP 800-38G. This
+    /// should only be used to decrypt data that was encrypted under that policy.
+    Legacy,
+}
+
+impl Default for DomainPolicy {
+    fn default() -> Self {
+        DomainPolicy::Strict
+    }
+}
+
 /// A struct for performing FF1 encryption and decryption operations.
-pub struct FF1<CIPH: BlockCipher> {
+///
+/// Block ciphers whose block size is not 128 bits are rejected:
+///
+/// ```compile_fail
+/// use aes::block_cipher::{
+///     generic_array::typenum::{U1, U16, U8},
+///     Block, BlockCipher, Key, NewBlockCipher,
+/// };
+/// use fpe::ff1::FF1;
+///
+/// #[derive(Clone)]
+/// struct Cipher64;
+///
+/// impl NewBlockCipher for Cipher64 {
+///     type KeySize = U16;
+///     fn new(_: &Key<Self>) -> Self {
+///         Cipher64
+///     }
+/// }
+///
+/// impl BlockCipher for Cipher64 {
+///     type BlockSize = U8;
+///     type ParBlocks = U1;
+///     fn encrypt_block(&self, _: &mut Block<Self>) {}
+///     fn decrypt_block(&self, _: &mut Block<Self>) {}
+/// }
+///
+/// let ff = FF1::<Cipher64>::new(&[0; 16], 10);
+/// ```
+#[derive(Clone)]
+pub struct FF1<CIPH: BlockCipher<BlockSize = U16>> {
     ciph: CIPH,
     radix: Radix,
+    policy: DomainPolicy,
+    max_tweak_len: usize,
 }
 
-impl<CIPH: NewBlockCipher + BlockCipher + Clone> FF1<CIPH> {
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> FF1<CIPH> {
     /// Creates a new FF1 object for the given key and radix.
     ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the given radix is not in [2..2^16].
+    pub fn new(key: &[u8], radix: u32) -> Result<Self, Error> {
+        Self::from_cipher(new_cipher(key)?, radix)
+    }
+
+    /// Creates a new FF1 object for the given typed key and radix.
+    ///
     /// Returns an error if the given radix is not in [2..2^16].
-    pub fn new(key: &[u8], radix: u32) -> Result<Self, ()> {
-        let ciph = CIPH::new(GenericArray::from_slice(key));
+    pub fn from_key(key: &Key<CIPH>, radix: u32) -> Result<Self, Error> {
+        Self::from_cipher(CIPH::new(key), radix)
+    }
+
+    /// Creates a new FF1 object for the given block cipher instance and radix.
+    ///
+    /// This allows the key schedule to be computed once, and shared with other uses of
+    /// the block cipher.
+    ///
+    /// Returns an error if the given radix is not in [2..2^16].
+    pub fn from_cipher(ciph: CIPH, radix: u32) -> Result<Self, Error> {
         let radix = Radix::from(radix)?;
-        Ok(FF1 { ciph, radix })
+        Ok(FF1 {
+            ciph,
+            radix,
+            policy: DomainPolicy::default(),
+            max_tweak_len: MAX_LEN,
+        })
+    }
+
+    /// Sets the policy for the minimum length of numeral strings.
+    pub fn with_domain_policy(mut self, policy: DomainPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the policy for the minimum length of numeral strings.
+    pub fn domain_policy(&self) -> DomainPolicy {
+        self.policy
+    }
+
+    /// Sets maxTlen, the maximum tweak length in bytes.
+    ///
+    /// The default, and the largest value that is accepted, is 2^32 - 1.
+    pub fn with_max_tweak_len(mut self, max_tweak_len: usize) -> Self {
+        self.max_tweak_len = cmp::min(max_tweak_len, MAX_LEN);
+        self
+    }
+
+    /// Returns maxTlen, the maximum tweak length in bytes.
+    pub fn max_tweak_len(&self) -> usize {
+        self.max_tweak_len
+    }
+
+    /// Returns minlen, the minimum length of numeral strings under the domain policy.
+    pub fn min_len(&self) -> usize {
+        match self.policy {
+            DomainPolicy::Strict => self.radix.min_len(),
+            DomainPolicy::Legacy => 2,
+        }
+    }
+
+    /// Returns maxlen, the maximum length of numeral strings.
+    pub fn max_len(&self) -> usize {
+        MAX_LEN
     }
 
     /// Encrypts the given numeral string.
     ///
-    /// Returns an error if the numeral string is not in the required radix.
-    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, ()> {
-        if !x.is_valid(self.radix.to_u32()) {
-            return Err(());
+    /// Returns an error if the numeral string is not in the required radix, if its
+    /// length is not in [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        self.check_lengths(x.len(), tweak.len())?;
+        ffx::encrypt(self, tweak, x)
+    }
+
+    /// Decrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, if its
+    /// length is not in [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        self.check_lengths(x.len(), tweak.len())?;
+        ffx::decrypt(self, tweak, x)
+    }
+
+    /// Prepares the state of FF1 for numeral strings of length n and the given tweak,
+    /// which can then encrypt and decrypt many numeral strings of that length.
+    ///
+    /// Returns an error if n is not in [minlen..maxlen], if n is too large for the
+    /// numeral string type, or if the tweak is longer than maxTlen.
+    pub fn prepare<NS: NumeralString>(
+        &self,
+        n: usize,
+        tweak: &[u8],
+    ) -> Result<FF1Context<CIPH, NS>, Error> {
+        self.check_lengths(n, tweak.len())?;
+        let max = NS::max_len(self.radix.to_u32());
+        if n > max {
+            return Err(Error::NumeralStringTooLong { len: n, max });
         }
+        Ok(FF1Context {
+            ff: self.clone(),
+            prefix: self.context(n, tweak),
+            halves: Halves::new(self, n),
+        })
+    }
 
-        let n = x.len();
-        let t = tweak.len();
+    fn check_lengths(&self, n: usize, t: usize) -> Result<(), Error> {
+        if n < self.min_len() && self.policy == DomainPolicy::Strict {
+            return Err(Error::DomainTooSmall);
+        }
+        if n < self.min_len() || n > self.max_len() {
+            return Err(Error::LengthOutOfBounds {
+                len: n,
+                min: self.min_len(),
+                max: self.max_len(),
+            });
+        }
+        if t > self.max_tweak_len {
+            return Err(Error::TweakTooLong {
+                len: t,
+                max: self.max_tweak_len,
+            });
+        }
+        Ok(())
+    }
 
+    /// Returns P, b and d for numeral strings of length n and tweaks of t bytes.
+    fn parameters(&self, n: usize, t: usize) -> ([u8; 16], usize, usize) {
         // 1. Let u = floor(n / 2); v = n - u
         let u = n / 2;
         let v = n - u;
 
-        // 2. Let A = X[1..u]; B = X[u + 1..n].
-        let (mut x_a, mut x_b) = x.split(u);
-
         // 3. Let b = ceil(ceil(v * log2(radix)) / 8).
         let b = self.radix.calculate_b(v);
 
@@ -213,129 +587,137 @@ impl<CIPH: NewBlockCipher + BlockCipher + Clone> FF1<CIPH> {
         p[8..12].copy_from_slice(&(n as u32).to_be_bytes());
         p[12..16].copy_from_slice(&(t as u32).to_be_bytes());
 
-        //  6i. Let Q = T || [0]^((-t-b-1) mod 16) || [i] || [NUM(B, radix)].
-        // 6ii. Let R = PRF(P || Q).
-        let mut prf = Prf::new(&self.ciph);
-        prf.update(&p);
-        prf.update(tweak);
-        for _ in 0..((((-(t as i32) - (b as i32) - 1) % 16) + 16) % 16) {
-            prf.update(&[0]);
-        }
-        for i in 0..10 {
-            let mut prf = prf.clone();
-            prf.update(&[i]);
-            prf.update(x_b.num_radix(self.radix.to_u32()).to_bytes(b).as_ref());
-            let r = prf.output();
-
-            // 6iii. Let S be the first d bytes of R.
-            let s = generate_s(&self.ciph, r, d);
-
-            // 6iv. Let y = NUM(S).
-            let y = NS::Num::from_bytes(s);
+        (p, b, d)
+    }
 
-            // 6v. If i is even, let m = u; else, let m = v.
-            let m = if i % 2 == 0 { u } else { v };
+    /// Returns y = NUM(S) for round i, given NUM(B, radix).
+    fn round_num<N: Numeral>(&self, context: &FF1Prefix<CIPH>, i: u8, num_b: &N) -> N {
+        let mut prf = context.prf.clone();
+        prf.update(&[i]);
+        prf.update(num_b.to_bytes(context.b).as_ref());
+        let r = prf.output();
 
-            // 6vi. Let c = (NUM(A, radix) + y) mod radix^m.
-            let c = x_a
-                .num_radix(self.radix.to_u32())
-                .add_mod_exp(y, self.radix.to_u32(), m);
+        // 6iii. Let S be the first d bytes of R.
+        let s = generate_s(&self.ciph, r, context.d);
 
-            // 6vii. Let C = STR(c, radix).
-            let x_c = NS::str_radix(c, self.radix.to_u32(), m);
+        // 6iv. Let y = NUM(S).
+        N::from_bytes(s)
+    }
+}
 
-            // 6viii. Let A = B.
-            x_a = x_b;
+/// The state of the FF1 round function for a given length and tweak.
+pub struct FF1Prefix<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> {
+    /// The PRF after processing P || T || [0]^((-t-b-1) mod 16).
+    prf: Prf<CIPH>,
+    b: usize,
+    d: usize,
+}
 
-            // 6ix. Let B = C.
-            x_b = x_c;
-        }
+/// The state of FF1 for numeral strings of a fixed length under a fixed tweak.
+///
+/// This caches the PRF state after P || T || [0]^((-t-b-1) mod 16), and the moduli
+/// radix^u and radix^v, so that they are not recomputed for every numeral string.
+/// It is created by `FF1::prepare`.
+pub struct FF1Context<CIPH, NS>
+where
+    CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>,
+    NS: NumeralString,
+{
+    ff: FF1<CIPH>,
+    prefix: FF1Prefix<CIPH>,
+    halves: Halves<NS::Num>,
+}
 
-        // 7. Return A || B.
-        Ok(NS::concat(x_a, x_b))
+impl<CIPH, NS> FF1Context<CIPH, NS>
+where
+    CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone,
+    NS: NumeralString,
+{
+    /// Encrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or if it
+    /// does not have the length that this context was prepared for.
+    pub fn encrypt(&self, x: &NS) -> Result<NS, Error> {
+        self.check(x)?;
+        Ok(ffx::encrypt_with(&self.ff, &self.prefix, &self.halves, x))
     }
 
     /// Decrypts the given numeral string.
     ///
-    /// Returns an error if the numeral string is not in the required radix.
-    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, ()> {
-        if !x.is_valid(self.radix.to_u32()) {
-            return Err(());
+    /// Returns an error if the numeral string is not in the required radix, or if it
+    /// does not have the length that this context was prepared for.
+    pub fn decrypt(&self, x: &NS) -> Result<NS, Error> {
+        self.check(x)?;
+        Ok(ffx::decrypt_with(&self.ff, &self.prefix, &self.halves, x))
+    }
+
+    fn check(&self, x: &NS) -> Result<(), Error> {
+        let n = self.halves.len();
+        if x.len() != n {
+            return Err(Error::LengthOutOfBounds {
+                len: x.len(),
+                min: n,
+                max: n,
+            });
         }
+        check_numerals(x, self.ff.radix.to_u32())
+    }
+}
 
-        let n = x.len();
-        let t = tweak.len();
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> Feistel for FF1<CIPH> {
+    type Context = FF1Prefix<CIPH>;
 
-        // 1. Let u = floor(n / 2); v = n - u
-        let u = n / 2;
-        let v = n - u;
+    fn radix(&self) -> u32 {
+        self.radix.to_u32()
+    }
 
-        // 2. Let A = X[1..u]; B = X[u + 1..n].
-        let (mut x_a, mut x_b) = x.split(u);
+    // 1. Let u = floor(n / 2); v = n - u
+    fn split(&self, n: usize) -> usize {
+        n / 2
+    }
 
-        // 3. Let b = ceil(ceil(v * log2(radix)) / 8).
-        let b = self.radix.calculate_b(v);
+    fn rounds(&self, _: usize) -> u8 {
+        10
+    }
 
-        // 4. Let d = 4 * ceil(b / 4) + 4.
-        let d = 4 * ((b + 3) / 4) + 4;
+    fn addition(&self) -> Addition {
+        Addition::Blockwise
+    }
 
-        // 5. Let P = [1, 2, 1] || [radix] || [10] || [u mod 256] || [n] || [t].
-        let mut p = [1, 2, 1, 0, 0, 0, 10, u as u8, 0, 0, 0, 0, 0, 0, 0, 0];
-        p[3..6].copy_from_slice(&self.radix.to_u32().to_be_bytes()[1..]);
-        p[8..12].copy_from_slice(&(n as u32).to_be_bytes());
-        p[12..16].copy_from_slice(&(t as u32).to_be_bytes());
+    fn context(&self, n: usize, tweak: &[u8]) -> FF1Prefix<CIPH> {
+        let (p, b, d) = self.parameters(n, tweak.len());
 
-        //  6i. Let Q = T || [0]^((-t-b-1) mod 16) || [i] || [NUM(A, radix)].
+        //  6i. Let Q = T || [0]^((-t-b-1) mod 16) || [i] || [NUM(B, radix)].
         // 6ii. Let R = PRF(P || Q).
         let mut prf = Prf::new(&self.ciph);
         prf.update(&p);
         prf.update(tweak);
-        for _ in 0..((((-(t as i32) - (b as i32) - 1) % 16) + 16) % 16) {
+        for _ in 0..q_padding(tweak.len(), b) {
             prf.update(&[0]);
         }
-        for i in 0..10 {
-            let i = 9 - i;
-            let mut prf = prf.clone();
-            prf.update(&[i]);
-            prf.update(x_a.num_radix(self.radix.to_u32()).to_bytes(b).as_ref());
-            let r = prf.output();
 
-            // 6iii. Let S be the first d bytes of R.
-            let s = generate_s(&self.ciph, r, d);
-
-            // 6iv. Let y = NUM(S).
-            let y = NS::Num::from_bytes(s);
-
-            // 6v. If i is even, let m = u; else, let m = v.
-            let m = if i % 2 == 0 { u } else { v };
-
-            // 6vi. Let c = (NUM(B, radix) - y) mod radix^m.
-            let c = x_b
-                .num_radix(self.radix.to_u32())
-                .sub_mod_exp(y, self.radix.to_u32(), m);
-
-            // 6vii. Let C = STR(c, radix).
-            let x_c = NS::str_radix(c, self.radix.to_u32(), m);
-
-            // 6viii. Let B = A.
-            x_b = x_a;
-
-            // 6ix. Let A = C.
-            x_a = x_c;
-        }
+        FF1Prefix { prf, b, d }
+    }
 
-        // 7. Return A || B.
-        Ok(NS::concat(x_a, x_b))
+    fn round<NS: NumeralString>(
+        &self,
+        context: &FF1Prefix<CIPH>,
+        _: usize,
+        i: u8,
+        x_b: &NS,
+    ) -> NS::Num {
+        self.round_num(context, i, &x_b.num_radix(self.radix.to_u32()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Radix;
+    use crate::Error;
 
     #[test]
     fn radix() {
-        assert_eq!(Radix::from(1), Err(()));
+        assert_eq!(Radix::from(1), Err(Error::InvalidRadix(1)));
         assert_eq!(
             Radix::from(2),
             Ok(Radix::PowerTwo {
@@ -376,6 +758,25 @@ mod tests {
                 log_radix: 16,
             })
         );
-        assert_eq!(Radix::from(65537), Err(()));
+        assert_eq!(Radix::from(65537), Err(Error::InvalidRadix(65537)));
+    }
+
+    #[test]
+    fn calculate_b() {
+        // Matches the floating-point definition.
+        for radix in (3..1000).chain(65500..65536) {
+            let r = Radix::from(radix).unwrap();
+            for v in (1..200).chain([1000, 4096, 65535, 1 << 20].iter().cloned()) {
+                let expected = (v as f64 * f64::from(radix).log2() / 8f64).ceil() as usize;
+                assert_eq!(r.calculate_b(v), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn min_len() {
+        for &(radix, min_len) in &[(2, 20), (10, 6), (26, 5), (36, 4), (100, 3), (65536, 2)] {
+            assert_eq!(Radix::from(radix).unwrap().min_len(), min_len);
+        }
     }
 }
@@ -0,0 +1,451 @@
+//! FF1 encryption of many numeral strings at once.
+//!
+//! Each round of FF1 computes a CBC-MAC, which is inherently sequential. The rounds of
+//! different numeral strings are independent, however, so this interleaves the rounds
+//! of up to `CIPH::ParBlocks` numeral strings, and encrypts their blocks together with
+//! `BlockCipher::encrypt_blocks`. Ciphers such as AES-NI pipeline those blocks.
+
+use aes::block_cipher::{
+    generic_array::typenum::{Unsigned, U16},
+    Block, BlockCipher, NewBlockCipher, ParBlocks,
+};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+use super::{check_numerals, q_padding, Numeral, NumeralString, FF1};
+use crate::ffx::{Feistel, Halves};
+use crate::Error;
+
+/// The tweaks of a batch of numeral strings.
+#[derive(Clone, Copy, Debug)]
+pub enum Tweaks<'a> {
+    /// The same tweak for every numeral string.
+    Shared(&'a [u8]),
+    /// One tweak for each numeral string, in the same order.
+    PerItem(&'a [&'a [u8]]),
+}
+
+impl<'a> Tweaks<'a> {
+    /// Returns the tweak of the i-th numeral string.
+    fn get(&self, i: usize) -> &'a [u8] {
+        match *self {
+            Tweaks::Shared(tweak) => tweak,
+            Tweaks::PerItem(tweaks) => tweaks[i],
+        }
+    }
+
+    /// Checks that there is one tweak for each of n numeral strings.
+    pub(super) fn check_len(&self, n: usize) -> Result<(), Error> {
+        match *self {
+            Tweaks::PerItem(tweaks) if tweaks.len() != n => Err(Error::LengthOutOfBounds {
+                len: tweaks.len(),
+                min: n,
+                max: n,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The state of FF1 for a run of numeral strings with the same length and tweak.
+struct Setup<'a, CIPH: BlockCipher<BlockSize = U16>, N> {
+    n: usize,
+    tweak: &'a [u8],
+    /// The CBC-MAC of the whole blocks of P || T || [0]^((-t-b-1) mod 16).
+    chain: Block<CIPH>,
+    /// The remaining bytes of P || T || [0]^((-t-b-1) mod 16), which precede [i] in
+    /// every round.
+    tail: Vec<u8>,
+    b: usize,
+    d: usize,
+    halves: Halves<N>,
+}
+
+/// A numeral string in the middle of encryption or decryption.
+struct Lane<NS> {
+    setup: usize,
+    x_a: NS,
+    x_b: NS,
+}
+
+/// Buffers that are reused across the rounds of a batch, with one entry per lane.
+struct Scratch<CIPH: BlockCipher<BlockSize = U16>> {
+    chains: Vec<Block<CIPH>>,
+    messages: Vec<Vec<u8>>,
+    s: Vec<Vec<u8>>,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> FF1<CIPH> {
+    /// Encrypts the given numeral strings.
+    ///
+    /// The result is identical to encrypting each numeral string with `FF1::encrypt`,
+    /// but the rounds of several numeral strings are interleaved, so that the block
+    /// cipher can process their blocks in parallel.
+    ///
+    /// Returns an error if `FF1::encrypt` would reject any of the numeral strings or
+    /// tweaks, or if there is not one tweak per numeral string.
+    pub fn encrypt_batch<NS: NumeralString>(
+        &self,
+        tweaks: Tweaks,
+        xs: &[NS],
+    ) -> Result<Vec<NS>, Error> {
+        self.batch(tweaks, xs, true)
+    }
+
+    /// Decrypts the given numeral strings.
+    ///
+    /// The result is identical to decrypting each numeral string with `FF1::decrypt`,
+    /// but the rounds of several numeral strings are interleaved, so that the block
+    /// cipher can process their blocks in parallel.
+    ///
+    /// Returns an error if `FF1::decrypt` would reject any of the numeral strings or
+    /// tweaks, or if there is not one tweak per numeral string.
+    pub fn decrypt_batch<NS: NumeralString>(
+        &self,
+        tweaks: Tweaks,
+        xs: &[NS],
+    ) -> Result<Vec<NS>, Error> {
+        self.batch(tweaks, xs, false)
+    }
+
+    fn batch<NS: NumeralString>(
+        &self,
+        tweaks: Tweaks,
+        xs: &[NS],
+        encrypt: bool,
+    ) -> Result<Vec<NS>, Error> {
+        tweaks.check_len(xs.len())?;
+        for (i, x) in xs.iter().enumerate() {
+            self.check_lengths(x.len(), tweaks.get(i).len())?;
+            check_numerals(x, self.radix.to_u32())?;
+        }
+
+        let (setups, indices) = self.setups(tweaks, xs);
+        let lanes = CIPH::ParBlocks::to_usize();
+        let mut scratch = Scratch {
+            chains: vec![Block::<CIPH>::default(); lanes],
+            messages: vec![Vec::new(); lanes],
+            s: vec![Vec::new(); lanes],
+        };
+
+        let mut res = Vec::with_capacity(xs.len());
+        for (xs, indices) in xs.chunks(lanes).zip(indices.chunks(lanes)) {
+            let mut group: Vec<Lane<NS>> = xs
+                .iter()
+                .zip(indices)
+                .map(|(x, &setup)| {
+                    let n = x.len();
+                    let u = self.split(n);
+                    // When encrypting, let A = X[1..u]; B = X[u + 1..n]. FF1 has an even
+                    // number of rounds, so A also has length u when decrypting.
+                    let (x_a, x_b) = x.split(u);
+                    Lane { setup, x_a, x_b }
+                })
+                .collect();
+
+            for r in 0..10 {
+                let i = if encrypt { r } else { 9 - r };
+                self.round_batch(&setups, &mut group, i, encrypt, &mut scratch);
+            }
+
+            // Return A || B.
+            res.extend(group.into_iter().map(|l| NS::concat(l.x_a, l.x_b)));
+        }
+        Ok(res)
+    }
+
+    /// Returns the state for each run of numeral strings with the same length and
+    /// tweak, and the index of the state of each numeral string.
+    fn setups<'a, NS: NumeralString>(
+        &self,
+        tweaks: Tweaks<'a>,
+        xs: &[NS],
+    ) -> (Vec<Setup<'a, CIPH, NS::Num>>, Vec<usize>) {
+        let mut setups: Vec<Setup<CIPH, NS::Num>> = Vec::new();
+        let mut indices = Vec::with_capacity(xs.len());
+        let mut prefixes = Vec::new();
+        for (i, x) in xs.iter().enumerate() {
+            let (n, tweak) = (x.len(), tweaks.get(i));
+            match setups.last() {
+                Some(s) if s.n == n && s.tweak == tweak => (),
+                _ => {
+                    let (p, b, d) = self.parameters(n, tweak.len());
+
+                    // P || T || [0]^((-t-b-1) mod 16) begins the input of the PRF in
+                    // every round.
+                    let mut prefix = p.to_vec();
+                    prefix.extend_from_slice(tweak);
+                    prefix.resize(prefix.len() + q_padding(tweak.len(), b), 0);
+                    let tail = prefix.split_off(prefix.len() / 16 * 16);
+                    prefixes.push(prefix);
+
+                    setups.push(Setup {
+                        n,
+                        tweak,
+                        chain: Block::<CIPH>::default(),
+                        tail,
+                        b,
+                        d,
+                        halves: Halves::new(self, n),
+                    });
+                }
+            }
+            indices.push(setups.len() - 1);
+        }
+
+        let lanes = CIPH::ParBlocks::to_usize();
+        for (setups, prefixes) in setups.chunks_mut(lanes).zip(prefixes.chunks(lanes)) {
+            let mut chains: Vec<_> = setups.iter().map(|s| s.chain).collect();
+            cbc_mac(&self.ciph, &mut chains, prefixes);
+            for (setup, chain) in setups.iter_mut().zip(chains) {
+                setup.chain = chain;
+            }
+        }
+
+        (setups, indices)
+    }
+
+    /// Performs round i of FF1 for each lane.
+    fn round_batch<NS: NumeralString>(
+        &self,
+        setups: &[Setup<CIPH, NS::Num>],
+        group: &mut [Lane<NS>],
+        i: u8,
+        encrypt: bool,
+        scratch: &mut Scratch<CIPH>,
+    ) {
+        let radix = self.radix.to_u32();
+        let k = group.len();
+
+        //  6i. Let Q = T || [0]^((-t-b-1) mod 16) || [i] || [NUM(B, radix)], where B is
+        //      the half that is not modified in this round.
+        for ((lane, chain), q) in group
+            .iter()
+            .zip(scratch.chains.iter_mut())
+            .zip(scratch.messages.iter_mut())
+        {
+            let setup = &setups[lane.setup];
+            let x_b = if encrypt { &lane.x_b } else { &lane.x_a };
+            *chain = setup.chain;
+            q.clear();
+            q.extend_from_slice(&setup.tail);
+            q.push(i);
+            q.extend_from_slice(x_b.num_radix(radix).to_bytes(setup.b).as_ref());
+        }
+
+        // 6ii. Let R = PRF(P || Q).
+        cbc_mac(&self.ciph, &mut scratch.chains[..k], &scratch.messages[..k]);
+
+        // 6iii. Let S be the first d bytes of R || CIPH(R ⊕ [1]^16) || ...
+        let ds: Vec<usize> = group.iter().map(|lane| setups[lane.setup].d).collect();
+        generate_s(&self.ciph, &scratch.chains[..k], &ds, &mut scratch.s[..k]);
+
+        for (lane, s) in group.iter_mut().zip(scratch.s.iter()) {
+            // 6iv. Let y = NUM(S).
+            let y = NS::Num::from_bytes(s.iter().cloned());
+
+            // If i is even, let m = u; else, let m = v.
+            let (m, modulus) = setups[lane.setup].halves.half(i);
+
+            if encrypt {
+                // Let C = A ⊞ y; A = B; B = C.
+                let c = lane.x_a.num_radix(radix).add_mod(y, modulus);
+                let x_c = NS::str_radix(c, radix, m);
+                lane.x_a = mem::replace(&mut lane.x_b, x_c);
+            } else {
+                // Let C = B ⊟ y; B = A; A = C.
+                let c = lane.x_b.num_radix(radix).sub_mod(y, modulus);
+                let x_c = NS::str_radix(c, radix, m);
+                lane.x_b = mem::replace(&mut lane.x_a, x_c);
+            }
+        }
+    }
+}
+
+/// Computes the CBC-MAC of each message, continuing from the corresponding chaining
+/// value, which it replaces.
+///
+/// The messages must consist of whole blocks, and there must be at most ParBlocks of
+/// them. The j-th blocks of all messages are encrypted together.
+fn cbc_mac<CIPH, M>(ciph: &CIPH, chains: &mut [Block<CIPH>], messages: &[M])
+where
+    CIPH: BlockCipher<BlockSize = U16>,
+    M: AsRef<[u8]>,
+{
+    let mut blocks = ParBlocks::<CIPH>::default();
+    let len = messages.iter().map(|m| m.as_ref().len()).max().unwrap_or(0);
+    for j in (0..len).step_by(16) {
+        for ((block, chain), m) in blocks.iter_mut().zip(chains.iter()).zip(messages) {
+            if let Some(data) = m.as_ref().get(j..j + 16) {
+                for ((x, c), d) in block.iter_mut().zip(chain).zip(data) {
+                    *x = c ^ d;
+                }
+            }
+        }
+        ciph.encrypt_blocks(&mut blocks);
+        for ((block, chain), m) in blocks.iter().zip(chains.iter_mut()).zip(messages) {
+            if j < m.as_ref().len() {
+                *chain = *block;
+            }
+        }
+    }
+}
+
+/// Writes the first d bytes of R || CIPH(R ⊕ [1]^16) || CIPH(R ⊕ [2]^16) || ... for
+/// each R and d, encrypting the j-th blocks of all lanes together.
+fn generate_s<CIPH: BlockCipher<BlockSize = U16>>(
+    ciph: &CIPH,
+    rs: &[Block<CIPH>],
+    ds: &[usize],
+    out: &mut [Vec<u8>],
+) {
+    for (s, r) in out.iter_mut().zip(rs) {
+        s.clear();
+        s.extend_from_slice(r);
+    }
+
+    let mut blocks = ParBlocks::<CIPH>::default();
+    let len = ds.iter().map(|d| (d + 15) / 16).max().unwrap_or(0);
+    for j in 1..len {
+        for (block, r) in blocks.iter_mut().zip(rs) {
+            block.clone_from(r);
+            for (b, j) in block.iter_mut().zip((j as u128).to_be_bytes().iter()) {
+                *b ^= j;
+            }
+        }
+        ciph.encrypt_blocks(&mut blocks);
+        for ((s, block), &d) in out.iter_mut().zip(blocks.iter()).zip(ds) {
+            if s.len() < d {
+                s.extend_from_slice(block);
+            }
+        }
+    }
+
+    for (s, &d) in out.iter_mut().zip(ds) {
+        s.truncate(d);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::Tweaks;
+    use crate::ff1::{DomainPolicy, FlexibleNumeralString, NumeralString, FF1};
+    use crate::Error;
+
+    /// Returns distinct numeral strings of the given lengths, shared with the parallel tests.
+    pub(crate) fn numeral_strings(radix: u32, lens: &[usize]) -> Vec<FlexibleNumeralString> {
+        lens.iter()
+            .enumerate()
+            .map(|(i, &n)| {
+                FlexibleNumeralString::from(
+                    (0..n)
+                        .map(|j| ((i * 31 + j * 7) % radix as usize) as u16)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_scalar() {
+        // Lengths on both sides of the u128 boundary and of d = 16, in runs of equal
+        // lengths and mixed together.
+        let lens = [
+            6, 6, 6, 9, 9, 16, 19, 38, 39, 40, 57, 58, 100, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 200,
+        ];
+        for &radix in [10, 36, 65536].iter() {
+            let ff = FF1::<Aes256>::new(&[7; 32], radix)
+                .unwrap()
+                .with_domain_policy(DomainPolicy::Legacy);
+            let xs = numeral_strings(radix, &lens);
+            let tweaks: Vec<Vec<u8>> = (0..lens.len()).map(|i| vec![i as u8; i]).collect();
+            let tweaks: Vec<&[u8]> = tweaks.iter().map(|t| &t[..]).collect();
+
+            for &batch_tweaks in [Tweaks::Shared(b"tweak"), Tweaks::PerItem(&tweaks)].iter() {
+                let cts = ff.encrypt_batch(batch_tweaks, &xs).unwrap();
+                assert_eq!(cts.len(), xs.len());
+                for (i, (x, ct)) in xs.iter().zip(&cts).enumerate() {
+                    let expected = ff.encrypt(batch_tweaks.get(i), x).unwrap();
+                    assert_eq!(ct.num_radix(radix), expected.num_radix(radix));
+                    assert_eq!(ct.len(), expected.len());
+                }
+
+                let pts = ff.decrypt_batch(batch_tweaks, &cts).unwrap();
+                for (x, pt) in xs.iter().zip(pts) {
+                    assert_eq!(pt.num_radix(radix), x.num_radix(radix));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_vectors() {
+        // NIST SP 800-38G FF1 samples 1 and 2, whose plaintexts are the same.
+        let ff = FF1::<Aes128>::new(
+            &[
+                0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+                0x4F, 0x3C,
+            ],
+            10,
+        )
+        .unwrap();
+        let pt = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let pts = [
+            FlexibleNumeralString::from(pt.clone()),
+            FlexibleNumeralString::from(pt),
+        ];
+        let tweak = [0x39, 0x38, 0x37, 0x36, 0x35, 0x34, 0x33, 0x32, 0x31, 0x30];
+        let tweaks = [&[][..], &tweak[..]];
+
+        let cts = ff.encrypt_batch(Tweaks::PerItem(&tweaks), &pts).unwrap();
+        let cts: Vec<Vec<u16>> = cts.into_iter().map(Vec::from).collect();
+        assert_eq!(
+            cts,
+            vec![
+                vec![2, 4, 3, 3, 4, 7, 7, 4, 8, 4],
+                vec![6, 1, 2, 4, 2, 0, 0, 7, 7, 3],
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let ff = FF1::<Aes256>::new(&[0; 32], 10).unwrap();
+        let xs = numeral_strings(10, &[6, 6, 6]);
+        assert!(ff
+            .encrypt_batch(Tweaks::Shared(&[]), &[] as &[FlexibleNumeralString])
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            ff.encrypt_batch(Tweaks::PerItem(&[&[], &[]]), &xs).err(),
+            Some(Error::LengthOutOfBounds {
+                len: 2,
+                min: 3,
+                max: 3,
+            })
+        );
+
+        let mut xs = xs;
+        xs.push(FlexibleNumeralString::from(vec![1, 2, 3, 4, 5, 10]));
+        assert_eq!(
+            ff.encrypt_batch(Tweaks::Shared(&[]), &xs).err(),
+            Some(Error::InvalidNumeral {
+                position: 5,
+                value: 10,
+                radix: 10,
+            })
+        );
+
+        xs[3] = FlexibleNumeralString::from(vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            ff.decrypt_batch(Tweaks::Shared(&[]), &xs).err(),
+            Some(Error::DomainTooSmall)
+        );
+    }
+}
@@ -0,0 +1,520 @@
+//! A constant-time arithmetic backend for FF1.
+//!
+//! The arithmetic of the other numeral strings takes time that depends on the values
+//! being encrypted: native arithmetic uses hardware division, whose latency varies with
+//! its operands on many CPUs, and `num-bigint` trims its integers to their significant
+//! limbs and divides them with data-dependent loops.
+//!
+//! `ConstantTimeNumeral` is instead an integer with a fixed number of 64-bit limbs,
+//! which only depends on public values: the radix, the lengths of the halves of the
+//! numeral string and the length of the round function output. Its operations never
+//! branch on, or index memory with, the value of an integer, and never divide it with
+//! hardware division. Reductions modulo radix^m are binary long divisions with a
+//! conditional subtraction per bit, and divisions by the radix multiply by a
+//! precomputed reciprocal.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::{cmp, ptr};
+
+use super::{Numeral, NumeralString};
+
+/// Returns the number of limbs of the integers less than radix^len.
+fn width(radix: u32, len: usize) -> usize {
+    // ceil(log2(radix)) bits per numeral.
+    let bits = len * (32 - (radix - 1).leading_zeros()) as usize;
+    cmp::max((bits + 63) / 64, 1)
+}
+
+/// Returns x, hiding its value from the optimizer.
+///
+/// Without this, LLVM recognizes masks that are derived from a carry or a borrow, and
+/// compiles the masked operations that use them into branches.
+fn barrier(x: u64) -> u64 {
+    // Safety: x is a local variable, which is valid and aligned for reads.
+    unsafe { ptr::read_volatile(&x) }
+}
+
+/// Returns a mask of all ones if bit is 1, and of all zeros if bit is 0.
+fn mask(bit: u64) -> u64 {
+    0u64.wrapping_sub(barrier(bit))
+}
+
+/// Returns the limb of x at index i, or zero if x has fewer limbs.
+fn limb(x: &[u64], i: usize) -> u64 {
+    x.get(i).cloned().unwrap_or(0)
+}
+
+/// Computes a += b, discarding the carry out of the limbs of a.
+fn add_assign(a: &mut [u64], b: &[u64]) {
+    let mut carry = 0;
+    for (i, a_i) in a.iter_mut().enumerate() {
+        let s = u128::from(*a_i) + u128::from(limb(b, i)) + u128::from(carry);
+        *a_i = s as u64;
+        carry = (s >> 64) as u64;
+    }
+}
+
+/// Computes a -= b, returning the borrow out of the limbs of a.
+fn sub_assign(a: &mut [u64], b: &[u64]) -> u64 {
+    let mut borrow = 0;
+    for (i, a_i) in a.iter_mut().enumerate() {
+        let d = u128::from(*a_i)
+            .wrapping_sub(u128::from(limb(b, i)))
+            .wrapping_sub(u128::from(borrow));
+        *a_i = d as u64;
+        borrow = (d >> 127) as u64;
+    }
+    borrow
+}
+
+/// Computes a = a * k + c, discarding the limb carried out of a.
+fn mul_add_small(a: &mut [u64], k: u64, c: u64) {
+    let mut carry = c;
+    for a_i in a.iter_mut() {
+        let p = u128::from(*a_i) * u128::from(k) + u128::from(carry);
+        *a_i = p as u64;
+        carry = (p >> 64) as u64;
+    }
+}
+
+/// Computes a = 2a + bit, discarding the bit shifted out of a.
+fn shl1(a: &mut [u64], bit: u64) {
+    let mut carry = bit;
+    for a_i in a.iter_mut() {
+        let next = *a_i >> 63;
+        *a_i = (*a_i << 1) | carry;
+        carry = next;
+    }
+}
+
+/// Subtracts modulus from a if a >= modulus.
+///
+/// a must have at least as many limbs as modulus.
+fn sub_if_ge(a: &mut [u64], modulus: &[u64]) {
+    // The borrow of a - modulus, computed without storing the difference.
+    let mut borrow = 0;
+    for (i, a_i) in a.iter().enumerate() {
+        let d = u128::from(*a_i)
+            .wrapping_sub(u128::from(limb(modulus, i)))
+            .wrapping_sub(u128::from(borrow));
+        borrow = (d >> 127) as u64;
+    }
+
+    let m = mask(1 ^ borrow);
+    let mut borrow = 0;
+    for (i, a_i) in a.iter_mut().enumerate() {
+        let d = u128::from(*a_i)
+            .wrapping_sub(u128::from(limb(modulus, i) & m))
+            .wrapping_sub(u128::from(borrow));
+        *a_i = d as u64;
+        borrow = (d >> 127) as u64;
+    }
+}
+
+/// Returns x mod modulus, with as many limbs as modulus.
+fn reduce(x: &[u64], modulus: &[u64]) -> Vec<u64> {
+    if let [m] = *modulus {
+        return vec![reduce_limb(x, m)];
+    }
+
+    // r < modulus before each step, so 2r + 1 < 2 * modulus fits in one more limb.
+    let mut r = vec![0; modulus.len() + 1];
+    for i in (0..x.len() * 64).rev() {
+        shl1(&mut r, (x[i / 64] >> (i % 64)) & 1);
+        sub_if_ge(&mut r, modulus);
+    }
+    r.truncate(modulus.len());
+    r
+}
+
+/// Returns x mod m, for a modulus m of a single limb.
+///
+/// This is the same computation as `reduce`, on native integers.
+fn reduce_limb(x: &[u64], m: u64) -> u64 {
+    let mut r = 0u64;
+    for i in (0..x.len() * 64).rev() {
+        // 2r + 1 < 2m may need 65 bits, but the result of each step is less than m.
+        let t = (u128::from(r) << 1) | u128::from((x[i / 64] >> (i % 64)) & 1);
+        let d = t.wrapping_sub(u128::from(m));
+        let ge = mask(1 ^ (d >> 127) as u64);
+        r = (d as u64 & ge) | (t as u64 & !ge);
+    }
+    r
+}
+
+/// Division by a public divisor d in [2..2^16], computed as a multiplication by a
+/// precomputed reciprocal followed by a shift.
+///
+/// With l = ceil(log2(d)), s = 48 + l and m = ceil(2^s / d), Granlund and Montgomery
+/// show that floor(x * m / 2^s) = floor(x / d) for every x < 2^48. m is at most 2^49, so
+/// x * m fits in 128 bits.
+struct Reciprocal {
+    d: u64,
+    m: u128,
+    s: u32,
+}
+
+impl Reciprocal {
+    fn new(d: u32) -> Self {
+        let s = 48 + (32 - (d - 1).leading_zeros());
+        let d = u128::from(d);
+        Reciprocal {
+            d: d as u64,
+            m: ((1u128 << s) + d - 1) / d,
+            s,
+        }
+    }
+
+    /// Returns floor(x / d) for x < 2^48.
+    fn div(&self, x: u64) -> u64 {
+        ((u128::from(x) * self.m) >> self.s) as u64
+    }
+
+    /// Computes x = floor(x / d), returning x mod d.
+    fn div_rem(&self, x: &mut [u64]) -> u64 {
+        // The remainder is less than 2^16, so each dividend of a remainder followed by
+        // 32 bits of x is less than 2^48.
+        let mut r = 0;
+        for x_i in x.iter_mut().rev() {
+            let hi = (r << 32) | (*x_i >> 32);
+            let q_hi = self.div(hi);
+            r = hi - q_hi * self.d;
+
+            let lo = (r << 32) | (*x_i & 0xffff_ffff);
+            let q_lo = self.div(lo);
+            r = lo - q_lo * self.d;
+
+            *x_i = (q_hi << 32) | q_lo;
+        }
+        r
+    }
+}
+
+/// An integer with a fixed number of limbs, whose arithmetic takes time independent of
+/// its value.
+#[derive(Clone)]
+pub struct ConstantTimeNumeral {
+    /// The limbs of the integer, least significant first.
+    limbs: Vec<u64>,
+}
+
+impl Numeral for ConstantTimeNumeral {
+    type Bytes = Vec<u8>;
+
+    fn from_bytes(s: impl Iterator<Item = u8>) -> Self {
+        let bytes: Vec<u8> = s.collect();
+        let mut limbs = vec![0; cmp::max((bytes.len() + 7) / 8, 1)];
+        for (i, b) in bytes.iter().rev().enumerate() {
+            limbs[i / 8] |= u64::from(*b) << (8 * (i % 8));
+        }
+        ConstantTimeNumeral { limbs }
+    }
+
+    fn to_bytes(&self, b: usize) -> Vec<u8> {
+        (0..b)
+            .rev()
+            .map(|i| (limb(&self.limbs, i / 8) >> (8 * (i % 8))) as u8)
+            .collect()
+    }
+
+    fn exp(radix: u32, m: usize) -> Self {
+        // radix^m may need one more bit than the integers less than it.
+        let mut limbs = vec![0; width(radix, m) + 1];
+        limbs[0] = 1;
+        for _ in 0..m {
+            mul_add_small(&mut limbs, u64::from(radix), 0);
+        }
+
+        // The modulus is public, so its width can depend on its value. Reductions take
+        // time proportional to its number of limbs.
+        while limbs.len() > 1 && limbs[limbs.len() - 1] == 0 {
+            limbs.pop();
+        }
+        ConstantTimeNumeral { limbs }
+    }
+
+    fn add_mod(self, other: Self, modulus: &Self) -> Self {
+        let m = &modulus.limbs;
+        let mut a = reduce(&self.limbs, m);
+        let b = reduce(&other.limbs, m);
+
+        // a + b < 2 * modulus, which one conditional subtraction reduces.
+        a.push(0);
+        add_assign(&mut a, &b);
+        sub_if_ge(&mut a, m);
+        a.truncate(m.len());
+        ConstantTimeNumeral { limbs: a }
+    }
+
+    fn sub_mod(self, other: Self, modulus: &Self) -> Self {
+        let m = &modulus.limbs;
+        let mut a = reduce(&self.limbs, m);
+        let b = reduce(&other.limbs, m);
+
+        // If a < b, the difference wrapped around, and adding modulus wraps it back.
+        let borrow = mask(sub_assign(&mut a, &b));
+        let m: Vec<u64> = m.iter().map(|m_i| m_i & borrow).collect();
+        add_assign(&mut a, &m);
+        ConstantTimeNumeral { limbs: a }
+    }
+}
+
+/// A numeral string that supports radixes in [2..2^16], and is encrypted with
+/// `ConstantTimeNumeral`.
+///
+/// Encryption and decryption take time that depends on the radix, the length of the
+/// numeral string and the length of the tweak, but not on the values of the numerals,
+/// provided that the block cipher is itself constant-time. They are a few times slower
+/// than with `FlexibleNumeralString`.
+pub struct ConstantTimeNumeralString(Vec<u16>);
+
+impl From<Vec<u16>> for ConstantTimeNumeralString {
+    fn from(v: Vec<u16>) -> Self {
+        ConstantTimeNumeralString(v)
+    }
+}
+
+impl From<ConstantTimeNumeralString> for Vec<u16> {
+    fn from(ns: ConstantTimeNumeralString) -> Self {
+        ns.0
+    }
+}
+
+impl NumeralString for ConstantTimeNumeralString {
+    type Num = ConstantTimeNumeral;
+
+    fn is_valid(&self, radix: u32) -> bool {
+        self.0.iter().all(|n| u32::from(*n) < radix)
+    }
+
+    fn invalid_numeral(&self, radix: u32) -> Option<(usize, u32)> {
+        self.0
+            .iter()
+            .map(|n| u32::from(*n))
+            .enumerate()
+            .find(|&(_, n)| n >= radix)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split(&self, u: usize) -> (Self, Self) {
+        let mut front = self.0.clone();
+        let back = front.split_off(u);
+        (
+            ConstantTimeNumeralString(front),
+            ConstantTimeNumeralString(back),
+        )
+    }
+
+    fn concat(mut a: Self, mut b: Self) -> Self {
+        a.0.append(&mut b.0);
+        a
+    }
+
+    fn rev(&self) -> Self {
+        let mut res = self.0.clone();
+        res.reverse();
+        ConstantTimeNumeralString(res)
+    }
+
+    fn num_radix(&self, radix: u32) -> ConstantTimeNumeral {
+        let mut limbs = vec![0; width(radix, self.0.len())];
+        for n in &self.0 {
+            mul_add_small(&mut limbs, u64::from(radix), u64::from(*n));
+        }
+        ConstantTimeNumeral { limbs }
+    }
+
+    fn str_radix(x: ConstantTimeNumeral, radix: u32, m: usize) -> Self {
+        let r = Reciprocal::new(radix);
+        let mut x = x.limbs;
+        let mut res = vec![0; m];
+        for n in res.iter_mut().rev() {
+            *n = r.div_rem(&mut x) as u16;
+        }
+        ConstantTimeNumeralString(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use num_bigint::BigUint;
+
+    use super::{ConstantTimeNumeral, ConstantTimeNumeralString, Reciprocal};
+    use crate::ff1::{DomainPolicy, FlexibleNumeralString, Numeral, NumeralString, FF1};
+
+    /// Returns pseudo-random bytes, for inputs that are not specific to any test.
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut x = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn big(x: &ConstantTimeNumeral, b: usize) -> BigUint {
+        BigUint::from_bytes_be(&x.to_bytes(b))
+    }
+
+    #[test]
+    fn reciprocal() {
+        for d in (2..=65536)
+            .step_by(97)
+            .chain([2, 3, 10, 255, 256, 65535, 65536].iter().cloned())
+        {
+            let r = Reciprocal::new(d);
+            let d = u64::from(d);
+            for &x in [0, 1, d - 1, d, d + 1, (1 << 48) - 1, (1 << 48) - d].iter() {
+                assert_eq!(r.div(x), x / d);
+            }
+            for q in [1, 2, 1000, ((1 << 48) - 1) / d].iter() {
+                assert_eq!(r.div(q * d - 1), q - 1);
+                assert_eq!(r.div(q * d), *q);
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        for &(radix, m, d) in [(10, 5, 8), (10, 19, 12), (2, 64, 12), (36, 100, 76)].iter() {
+            let modulus = ConstantTimeNumeral::exp(radix, m);
+            let modulus_big = num_traits::pow(BigUint::from(radix), m);
+            let b = (modulus.limbs.len() + 1) * 8;
+            assert_eq!(big(&modulus, b), modulus_big);
+
+            for seed in 0..50 {
+                let a = BigUint::from_bytes_be(&bytes(seed, b)) % &modulus_big;
+                let a_bytes = a.to_bytes_be();
+                let y_bytes = bytes(seed + 1000, d);
+                let y = BigUint::from_bytes_be(&y_bytes);
+
+                let a_ct = ConstantTimeNumeral::from_bytes(a_bytes.iter().cloned());
+                let y_ct = ConstantTimeNumeral::from_bytes(y_bytes.iter().cloned());
+                assert_eq!(big(&y_ct, d), y);
+
+                let sum = a_ct.clone().add_mod(y_ct.clone(), &modulus);
+                assert_eq!(big(&sum, b), (&a + &y) % &modulus_big);
+                let diff = sum.sub_mod(y_ct, &modulus);
+                assert_eq!(big(&diff, b), a);
+            }
+        }
+    }
+
+    #[test]
+    fn radix_conversion() {
+        for &radix in [2, 10, 36, 256, 65535, 65536].iter() {
+            for &len in [1, 6, 19, 39, 100].iter() {
+                let numerals: Vec<u16> = bytes(u64::from(radix) + len as u64, 2 * len)
+                    .chunks(2)
+                    .map(|c| ((u32::from(c[0]) << 8 | u32::from(c[1])) % radix) as u16)
+                    .collect();
+                let b = (len * 17 + 7) / 8;
+
+                let ns = ConstantTimeNumeralString::from(numerals.clone());
+                let flexible = FlexibleNumeralString::from(numerals.clone());
+                assert_eq!(
+                    ns.num_radix(radix).to_bytes(b),
+                    flexible.num_radix(radix).to_bytes(b).as_ref()
+                );
+                assert_eq!(
+                    Vec::from(ConstantTimeNumeralString::str_radix(
+                        ns.num_radix(radix),
+                        radix,
+                        len
+                    )),
+                    numerals
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vectors() {
+        // NIST SP 800-38G FF1 samples 1, 2 and 9.
+        let key = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let ff = FF1::<Aes128>::new(&key, 10).unwrap();
+        let pt = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        for &(tweak, ct) in [
+            (&[][..], [2, 4, 3, 3, 4, 7, 7, 4, 8, 4]),
+            (
+                &[0x39, 0x38, 0x37, 0x36, 0x35, 0x34, 0x33, 0x32, 0x31, 0x30][..],
+                [6, 1, 2, 4, 2, 0, 0, 7, 7, 3],
+            ),
+        ]
+        .iter()
+        {
+            let res = ff
+                .encrypt(tweak, &ConstantTimeNumeralString::from(pt.clone()))
+                .unwrap();
+            assert_eq!(Vec::from(res), ct.to_vec());
+            let res = ff
+                .decrypt(tweak, &ConstantTimeNumeralString::from(ct.to_vec()))
+                .unwrap();
+            assert_eq!(Vec::from(res), pt);
+        }
+
+        let key = [
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C, 0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F,
+            0x04, 0xFC, 0x6A, 0x94,
+        ];
+        let ff = FF1::<Aes256>::new(&key, 36).unwrap();
+        let tweak = [
+            0x37, 0x37, 0x37, 0x37, 0x70, 0x71, 0x72, 0x73, 0x37, 0x37, 0x37,
+        ];
+        let pt: Vec<u16> = (0..19).collect();
+        let ct = vec![
+            33, 28, 8, 10, 0, 10, 35, 17, 2, 10, 31, 34, 10, 21, 34, 35, 30, 32, 13,
+        ];
+        let res = ff
+            .encrypt(&tweak, &ConstantTimeNumeralString::from(pt.clone()))
+            .unwrap();
+        assert_eq!(Vec::from(res), ct);
+        let res = ff
+            .decrypt(&tweak, &ConstantTimeNumeralString::from(ct))
+            .unwrap();
+        assert_eq!(Vec::from(res), pt);
+    }
+
+    #[test]
+    fn matches_flexible() {
+        for &radix in [2, 10, 36, 65536].iter() {
+            let ff = FF1::<Aes256>::new(&[3; 32], radix)
+                .unwrap()
+                .with_domain_policy(DomainPolicy::Legacy);
+            for &len in [6, 9, 16, 19, 38, 39, 40, 100, 200].iter() {
+                let numerals: Vec<u16> = (0..len)
+                    .map(|i| ((i * 7919 + len) as u32 % radix) as u16)
+                    .collect();
+                let tweak = bytes(len as u64, len % 13);
+
+                let expected = ff
+                    .encrypt(&tweak, &FlexibleNumeralString::from(numerals.clone()))
+                    .unwrap();
+                let ct = ff
+                    .encrypt(&tweak, &ConstantTimeNumeralString::from(numerals.clone()))
+                    .unwrap();
+                let ct = Vec::from(ct);
+                assert_eq!(ct, Vec::from(expected));
+
+                let pt = ff
+                    .decrypt(&tweak, &ConstantTimeNumeralString::from(ct))
+                    .unwrap();
+                assert_eq!(Vec::from(pt), numerals);
+            }
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! FF1 encryption of numeral strings in borrowed buffers.
+//!
+//! The two halves of the numeral string are rewritten in place. Round i of FF1 replaces
+//! A with C = A ⊞ F(B), and then swaps A and B, so A is X[1..u] in even rounds and
+//! X[u + 1..n] in odd rounds. After the ten rounds, A and B are back where they
+//! started, and X[1..u] || X[u + 1..n] is the ciphertext.
+//!
+//! When radix^v is small enough for the round function output to fit in 128 bits, the
+//! arithmetic uses `u128`, and nothing is allocated.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use core::ops::Range;
+
+use super::{native, FF1Prefix, Numeral, FF1};
+use crate::ffx::{Feistel, Halves};
+use crate::Error;
+
+/// The largest d for which NUM(S) fits in a `u128`.
+const MAX_NATIVE_D: usize = 16;
+
+/// Integers that can be read from and written to part of a borrowed buffer.
+pub(crate) trait BufferNumeral: Numeral {
+    /// Returns NUM_radix(X) for the given numerals.
+    fn from_numerals(x: &[u16], radix: u32) -> Self;
+
+    /// Writes STR^m_radix(self) to the given numerals, where m = x.len().
+    fn to_numerals(self, radix: u32, x: &mut [u16]);
+
+    /// Returns NUM_2(X) for the given range of bits of x. See `bit` for the bit order.
+    fn from_bits(x: &[u8], bits: Range<usize>) -> Self;
+
+    /// Writes STR^m_2(self) to the given range of bits of x, where m is the length of
+    /// the range. See `bit` for the bit order.
+    fn to_bits(self, x: &mut [u8], bits: Range<usize>);
+}
+
+/// Returns bit i of x, which is bit (i mod 8) of byte floor(i / 8), as in
+/// `BinaryNumeralString::from_bytes_le`.
+pub(crate) fn bit(x: &[u8], i: usize) -> u8 {
+    (x[i / 8] >> (i % 8)) & 1
+}
+
+/// Sets bit i of x to the given value. See `bit` for the bit order.
+pub(crate) fn set_bit(x: &mut [u8], i: usize, value: u8) {
+    x[i / 8] = (x[i / 8] & !(1 << (i % 8))) | ((value & 1) << (i % 8));
+}
+
+impl BufferNumeral for u128 {
+    fn from_numerals(x: &[u16], radix: u32) -> Self {
+        native::num_radix(x, radix)
+    }
+
+    fn to_numerals(self, radix: u32, x: &mut [u16]) {
+        native::str_radix(self, radix, x)
+    }
+
+    fn from_bits(x: &[u8], bits: Range<usize>) -> Self {
+        bits.fold(0, |acc, i| (acc << 1) | u128::from(bit(x, i)))
+    }
+
+    fn to_bits(self, x: &mut [u8], bits: Range<usize>) {
+        for (j, i) in bits.rev().enumerate() {
+            set_bit(x, i, (self >> j) as u8);
+        }
+    }
+}
+
+/// A numeral string stored in a borrowed buffer.
+trait NumeralBuffer {
+    /// Returns the number of numerals.
+    fn len(&self) -> usize;
+
+    /// Checks that every numeral is valid for the radix.
+    fn check(&self, radix: u32) -> Result<(), Error>;
+
+    /// Returns NUM_radix of the numerals in the given range.
+    fn num<N: BufferNumeral>(&self, range: Range<usize>, radix: u32) -> N;
+
+    /// Writes STR^m_radix(x) to the numerals in the given range, where m is the length
+    /// of the range.
+    fn write<N: BufferNumeral>(&mut self, x: N, range: Range<usize>, radix: u32);
+}
+
+/// A numeral string with one numeral per element.
+impl NumeralBuffer for [u16] {
+    fn len(&self) -> usize {
+        <[u16]>::len(self)
+    }
+
+    fn check(&self, radix: u32) -> Result<(), Error> {
+        match self
+            .iter()
+            .map(|n| u32::from(*n))
+            .enumerate()
+            .find(|&(_, n)| n >= radix)
+        {
+            Some((position, value)) => Err(Error::InvalidNumeral {
+                position,
+                value,
+                radix,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn num<N: BufferNumeral>(&self, range: Range<usize>, radix: u32) -> N {
+        N::from_numerals(&self[range], radix)
+    }
+
+    fn write<N: BufferNumeral>(&mut self, x: N, range: Range<usize>, radix: u32) {
+        x.to_numerals(radix, &mut self[range])
+    }
+}
+
+/// A binary numeral string with eight numerals per byte. See `bit` for the bit order.
+impl NumeralBuffer for [u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self) * 8
+    }
+
+    fn check(&self, radix: u32) -> Result<(), Error> {
+        if radix != 2 {
+            return Err(Error::InvalidRadix(radix));
+        }
+        Ok(())
+    }
+
+    fn num<N: BufferNumeral>(&self, range: Range<usize>, _: u32) -> N {
+        N::from_bits(self, range)
+    }
+
+    fn write<N: BufferNumeral>(&mut self, x: N, range: Range<usize>, _: u32) {
+        x.to_bits(self, range)
+    }
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> FF1<CIPH> {
+    /// Encrypts the given numerals in place.
+    ///
+    /// Nothing is allocated unless the round function output is longer than 16 bytes,
+    /// which happens when radix^ceil(n / 2) is larger than 2^96. Without the `alloc`
+    /// feature, such numeral strings are rejected.
+    ///
+    /// Returns an error if the numerals are not in the required radix, if their
+    /// number is not in [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn encrypt_in_place(&self, tweak: &[u8], x: &mut [u16]) -> Result<(), Error> {
+        self.in_place(tweak, x, true)
+    }
+
+    /// Decrypts the given numerals in place.
+    ///
+    /// Nothing is allocated unless the round function output is longer than 16 bytes,
+    /// which happens when radix^ceil(n / 2) is larger than 2^96. Without the `alloc`
+    /// feature, such numeral strings are rejected.
+    ///
+    /// Returns an error if the numerals are not in the required radix, if their
+    /// number is not in [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn decrypt_in_place(&self, tweak: &[u8], x: &mut [u16]) -> Result<(), Error> {
+        self.in_place(tweak, x, false)
+    }
+
+    /// Encrypts the given bytes in place, as a binary numeral string of 8 * x.len()
+    /// numerals in the bit order of `BinaryNumeralString::from_bytes_le`.
+    ///
+    /// Nothing is allocated unless x is longer than 24 bytes. Without the `alloc`
+    /// feature, longer inputs are rejected.
+    ///
+    /// Returns an error if the radix is not 2, if the number of bits is not in
+    /// [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn encrypt_bytes_in_place(&self, tweak: &[u8], x: &mut [u8]) -> Result<(), Error> {
+        self.in_place(tweak, x, true)
+    }
+
+    /// Decrypts the given bytes in place, as a binary numeral string of 8 * x.len()
+    /// numerals in the bit order of `BinaryNumeralString::from_bytes_le`.
+    ///
+    /// Nothing is allocated unless x is longer than 24 bytes. Without the `alloc`
+    /// feature, longer inputs are rejected.
+    ///
+    /// Returns an error if the radix is not 2, if the number of bits is not in
+    /// [minlen..maxlen], or if the tweak is longer than maxTlen.
+    pub fn decrypt_bytes_in_place(&self, tweak: &[u8], x: &mut [u8]) -> Result<(), Error> {
+        self.in_place(tweak, x, false)
+    }
+
+    fn in_place<B: NumeralBuffer + ?Sized>(
+        &self,
+        tweak: &[u8],
+        x: &mut B,
+        encrypt: bool,
+    ) -> Result<(), Error> {
+        let n = x.len();
+        self.check_lengths(n, tweak.len())?;
+        x.check(self.radix.to_u32())?;
+
+        let context = self.context(n, tweak);
+        if context.d <= MAX_NATIVE_D {
+            self.feistel_in_place::<u128, B>(&context, x, encrypt);
+            return Ok(());
+        }
+
+        #[cfg(feature = "alloc")]
+        {
+            self.feistel_in_place::<num_bigint::BigUint, B>(&context, x, encrypt);
+            Ok(())
+        }
+        #[cfg(not(feature = "alloc"))]
+        Err(Error::LengthOutOfBounds {
+            len: n,
+            min: self.min_len(),
+            max: (1..)
+                .take_while(|&len| self.parameters(len, 0).2 <= MAX_NATIVE_D)
+                .last()
+                .unwrap_or(0),
+        })
+    }
+
+    fn feistel_in_place<N: BufferNumeral, B: NumeralBuffer + ?Sized>(
+        &self,
+        context: &FF1Prefix<CIPH>,
+        x: &mut B,
+        encrypt: bool,
+    ) {
+        let radix = self.radix.to_u32();
+        let n = x.len();
+        let u = self.split(n);
+        let halves = Halves::<N>::new(self, n);
+
+        for r in 0..10 {
+            let i = if encrypt { r } else { 9 - r };
+
+            // A is X[1..u] in even rounds and X[u + 1..n] in odd rounds.
+            let (a, b) = if i % 2 == 0 {
+                (0..u, u..n)
+            } else {
+                (u..n, 0..u)
+            };
+            let (_, modulus) = halves.half(i);
+
+            // Let y = F(n, T, i, B), and replace A with A ⊞ y when encrypting, or with
+            // A ⊟ y when decrypting.
+            let y = self.round_num(context, i, &x.num::<N>(b, radix));
+            let c = x.num::<N>(a.clone(), radix);
+            let c = if encrypt {
+                c.add_mod(y, modulus)
+            } else {
+                c.sub_mod(y, modulus)
+            };
+            x.write(c, a, radix);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::ff1::{BinaryNumeralString, DomainPolicy, FlexibleNumeralString, FF1};
+    use crate::Error;
+
+    #[test]
+    fn test_vectors() {
+        // NIST SP 800-38G FF1 samples 1 and 2.
+        let ff = FF1::<Aes128>::new(
+            &[
+                0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+                0x4F, 0x3C,
+            ],
+            10,
+        )
+        .unwrap();
+        let mut x = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        ff.encrypt_in_place(&[], &mut x).unwrap();
+        assert_eq!(x, [2, 4, 3, 3, 4, 7, 7, 4, 8, 4]);
+        ff.decrypt_in_place(&[], &mut x).unwrap();
+        assert_eq!(x, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let tweak = [0x39, 0x38, 0x37, 0x36, 0x35, 0x34, 0x33, 0x32, 0x31, 0x30];
+        ff.encrypt_in_place(&tweak, &mut x).unwrap();
+        assert_eq!(x, [6, 1, 2, 4, 2, 0, 0, 7, 7, 3]);
+    }
+
+    #[test]
+    fn matches_numeral_strings() {
+        // Both odd and even lengths, on both sides of the u128 boundary.
+        for &radix in [10, 36, 65536].iter() {
+            let ff = FF1::<Aes256>::new(&[3; 32], radix)
+                .unwrap()
+                .with_domain_policy(DomainPolicy::Legacy);
+            for &n in [2, 7, 16, 19, 57, 58, 59, 101].iter() {
+                let pt: Vec<u16> = (0..n).map(|i| ((i * 13 + 5) % radix) as u16).collect();
+                let expected = Vec::from(
+                    ff.encrypt(b"tweak", &FlexibleNumeralString::from(pt.clone()))
+                        .unwrap(),
+                );
+
+                let mut x = pt.clone();
+                ff.encrypt_in_place(b"tweak", &mut x).unwrap();
+                assert_eq!(x, expected);
+                ff.decrypt_in_place(b"tweak", &mut x).unwrap();
+                assert_eq!(x, pt);
+            }
+        }
+
+        let ff = FF1::<Aes256>::new(&[3; 32], 2).unwrap();
+        for &len in [3, 4, 13, 24, 25, 100].iter() {
+            let pt: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            let expected = ff
+                .encrypt(&[], &BinaryNumeralString::from_bytes_le(&pt))
+                .unwrap()
+                .to_bytes_le();
+
+            let mut x = pt.clone();
+            ff.encrypt_bytes_in_place(&[], &mut x).unwrap();
+            assert_eq!(x, expected);
+            ff.decrypt_bytes_in_place(&[], &mut x).unwrap();
+            assert_eq!(x, pt);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let ff = FF1::<Aes256>::new(&[0; 32], 10).unwrap();
+        let mut x = vec![1, 2, 3, 4, 5, 10];
+        assert_eq!(
+            ff.encrypt_in_place(&[], &mut x),
+            Err(Error::InvalidNumeral {
+                position: 5,
+                value: 10,
+                radix: 10,
+            })
+        );
+        assert_eq!(x, [1, 2, 3, 4, 5, 10]);
+        assert_eq!(
+            ff.encrypt_in_place(&[], &mut [0; 5]),
+            Err(Error::DomainTooSmall)
+        );
+        assert_eq!(
+            ff.encrypt_bytes_in_place(&[], &mut [0; 4]),
+            Err(Error::InvalidRadix(10))
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! FF1 Numeral and NumeralString implementations that do not require a global
+//! allocator.
+
+use core::convert::TryFrom;
+
+use super::{Numeral, NumeralString};
+
+/// Returns radix^m, or `None` if it does not fit in 128 bits.
+pub(crate) fn checked_pow(radix: u32, m: usize) -> Option<u128> {
+    u32::try_from(m)
+        .ok()
+        .and_then(|m| u128::from(radix).checked_pow(m))
+}
+
+/// Returns the largest m for which radix^m fits in 128 bits.
+fn max_exp(radix: u32) -> usize {
+    let mut m = 0;
+    while checked_pow(radix, m + 1).is_some() {
+        m += 1;
+    }
+    m
+}
+
+/// Returns the number that the given numerals represent in the base radix. The caller
+/// must ensure that radix^n fits in 128 bits.
+pub(crate) fn num_radix(numerals: &[u16], radix: u32) -> u128 {
+    if radix.is_power_of_two() {
+        // Each numeral is log2(radix) bits of the result.
+        let k = radix.trailing_zeros();
+        return numerals
+            .iter()
+            .fold(0, |acc, i| (acc << k) | u128::from(*i));
+    }
+
+    let radix = u128::from(radix);
+    numerals
+        .iter()
+        .fold(0, |acc, i| acc * radix + u128::from(*i))
+}
+
+/// Writes the representation of x in the base radix to the given numerals.
+pub(crate) fn str_radix(mut x: u128, radix: u32, numerals: &mut [u16]) {
+    if radix.is_power_of_two() {
+        let k = radix.trailing_zeros();
+        for numeral in numerals.iter_mut().rev() {
+            *numeral = (x & u128::from(radix - 1)) as u16;
+            x >>= k;
+        }
+        return;
+    }
+
+    // Divide as a u64 once x fits in 64 bits, which is much faster.
+    let mut i = numerals.len();
+    while x >> 64 != 0 && i > 0 {
+        i -= 1;
+        numerals[i] = (x % u128::from(radix)) as u16;
+        x /= u128::from(radix);
+    }
+    let mut x = x as u64;
+    while i > 0 {
+        i -= 1;
+        numerals[i] = (x % u64::from(radix)) as u16;
+        x /= u64::from(radix);
+    }
+}
+
+/// The big-endian byte representation of a `u128`, zero-padded to a given length of at
+/// most 16 bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct NativeBytes {
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl AsRef<[u8]> for NativeBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf[16 - self.len..]
+    }
+}
+
+/// Integers that fit in 128 bits. Callers must ensure that radix^m fits in 128 bits, and
+/// that the byte strings passed to `from_bytes` encode integers that fit in 128 bits.
+impl Numeral for u128 {
+    type Bytes = NativeBytes;
+
+    fn from_bytes(s: impl Iterator<Item = u8>) -> Self {
+        s.fold(0, |acc, b| {
+            assert!(acc >> 120 == 0, "integer must fit in 128 bits");
+            (acc << 8) | u128::from(b)
+        })
+    }
+
+    fn to_bytes(&self, b: usize) -> NativeBytes {
+        assert!(b <= 16, "u128 byte representations are at most 16 bytes");
+        assert!(
+            b == 16 || *self >> (8 * b) == 0,
+            "integer must fit in {} bytes",
+            b
+        );
+        NativeBytes {
+            buf: self.to_be_bytes(),
+            len: b,
+        }
+    }
+
+    fn exp(radix: u32, m: usize) -> Self {
+        checked_pow(radix, m).expect("radix^m must fit in 128 bits")
+    }
+
+    fn add_mod(self, other: Self, modulus: &Self) -> Self {
+        if modulus.is_power_of_two() {
+            // The modulus divides 2^128, so the sum can wrap before it is masked.
+            return self.wrapping_add(other) & (modulus - 1);
+        }
+
+        let (a, b) = (self % modulus, other % modulus);
+        // a + b overflows when the modulus is larger than 2^127, but a - (modulus - b)
+        // does not.
+        if a >= modulus - b {
+            a - (modulus - b)
+        } else {
+            a + b
+        }
+    }
+
+    fn sub_mod(self, other: Self, modulus: &Self) -> Self {
+        if modulus.is_power_of_two() {
+            return self.wrapping_sub(other) & (modulus - 1);
+        }
+
+        let (a, b) = (self % modulus, other % modulus);
+        if a >= b {
+            a - b
+        } else {
+            modulus - (b - a)
+        }
+    }
+}
+
+/// A numeral string of at most N numerals, stored on the stack, that supports radixes
+/// in [2..2^16).
+///
+/// Numeric operations use `u128`, so numeral strings can only be encrypted if
+/// radix^n fits in 128 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArrayNumeralString<const N: usize> {
+    numerals: [u16; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayNumeralString<N> {
+    /// Creates a numeral string from the given numerals.
+    ///
+    /// Returns `None` if there are more than N numerals.
+    pub fn from_slice(numerals: &[u16]) -> Option<Self> {
+        if numerals.len() > N {
+            return None;
+        }
+        let mut res = ArrayNumeralString {
+            numerals: [0; N],
+            len: numerals.len(),
+        };
+        res.numerals[..numerals.len()].copy_from_slice(numerals);
+        Some(res)
+    }
+
+    /// Returns the numerals of this numeral string.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.numerals[..self.len]
+    }
+}
+
+impl<const N: usize> From<[u16; N]> for ArrayNumeralString<N> {
+    fn from(numerals: [u16; N]) -> Self {
+        ArrayNumeralString { numerals, len: N }
+    }
+}
+
+impl<const N: usize> AsRef<[u16]> for ArrayNumeralString<N> {
+    fn as_ref(&self) -> &[u16] {
+        self.as_slice()
+    }
+}
+
+impl<const N: usize> NumeralString for ArrayNumeralString<N> {
+    type Num = u128;
+
+    fn is_valid(&self, radix: u32) -> bool {
+        self.as_slice().iter().all(|n| u32::from(*n) < radix)
+    }
+
+    fn invalid_numeral(&self, radix: u32) -> Option<(usize, u32)> {
+        self.as_slice()
+            .iter()
+            .map(|n| u32::from(*n))
+            .enumerate()
+            .find(|&(_, n)| n >= radix)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn max_len(radix: u32) -> usize {
+        max_exp(radix).min(N)
+    }
+
+    fn split(&self, u: usize) -> (Self, Self) {
+        let (front, back) = self.as_slice().split_at(u);
+        (
+            Self::from_slice(front).unwrap(),
+            Self::from_slice(back).unwrap(),
+        )
+    }
+
+    fn concat(mut a: Self, b: Self) -> Self {
+        assert!(a.len + b.len <= N, "numeral string must fit in N numerals");
+        a.numerals[a.len..a.len + b.len].copy_from_slice(b.as_slice());
+        a.len += b.len;
+        a
+    }
+
+    fn rev(&self) -> Self {
+        let mut res = *self;
+        res.numerals[..res.len].reverse();
+        res
+    }
+
+    fn num_radix(&self, radix: u32) -> u128 {
+        num_radix(self.as_slice(), radix)
+    }
+
+    fn str_radix(x: u128, radix: u32, m: usize) -> Self {
+        assert!(m <= N, "numeral string must fit in N numerals");
+        let mut res = ArrayNumeralString {
+            numerals: [0; N],
+            len: m,
+        };
+        str_radix(x, radix, &mut res.numerals[..m]);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes128;
+    #[cfg(feature = "alloc")]
+    use num_bigint::BigUint;
+
+    use super::{checked_pow, ArrayNumeralString};
+    use crate::ff1::{Numeral, NumeralString, FF1};
+    use crate::Error;
+
+    #[test]
+    fn pow() {
+        assert_eq!(checked_pow(10, 0), Some(1));
+        assert_eq!(checked_pow(10, 38), Some(10u128.pow(38)));
+        assert_eq!(checked_pow(10, 39), None);
+        assert_eq!(checked_pow(2, 127), Some(1 << 127));
+        assert_eq!(checked_pow(2, 128), None);
+        assert_eq!(checked_pow(65536, 7), Some(1 << 112));
+        assert_eq!(checked_pow(65536, 8), None);
+    }
+
+    #[test]
+    fn bytes() {
+        let x = u128::from_bytes([1, 2, 3].iter().cloned());
+        assert_eq!(x, 0x01_0203);
+        assert_eq!(x.to_bytes(3).as_ref(), &[1, 2, 3]);
+        assert_eq!(x.to_bytes(5).as_ref(), &[0, 0, 1, 2, 3]);
+        assert_eq!(0u128.to_bytes(0).as_ref(), &[] as &[u8]);
+        assert_eq!(u128::max_value().to_bytes(16).as_ref(), &[0xff; 16]);
+        assert_eq!(
+            u128::from_bytes([0xff; 16].iter().cloned()),
+            u128::max_value()
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn mod_exp() {
+        // Compare against BigUint, including moduli larger than 2^127 where the sum of
+        // two residues overflows.
+        let cases = [
+            (10, 9, 123_456_789, 999_999_999),
+            (10, 19, 9_999_999_999_999_999_998, 17),
+            (2, 127, u128::max_value(), u128::max_value() - 1),
+            (5, 55, 5u128.pow(55) - 1, u128::max_value()),
+            (36, 24, 0, 1),
+        ];
+        for &(radix, m, a, b) in cases.iter() {
+            let big = |x: u128| BigUint::from(x);
+            let expected = |x: BigUint| u128::from_bytes(x.to_bytes(16).into_iter());
+
+            assert_eq!(
+                a.add_mod_exp(b, radix, m),
+                expected(big(a).add_mod_exp(big(b), radix, m))
+            );
+            assert_eq!(
+                a.sub_mod_exp(b, radix, m),
+                expected(big(a).sub_mod_exp(big(b), radix, m))
+            );
+            assert_eq!(
+                b.sub_mod_exp(a, radix, m),
+                expected(big(b).sub_mod_exp(big(a), radix, m))
+            );
+        }
+    }
+
+    #[test]
+    fn array_numeral_string() {
+        let ns = ArrayNumeralString::<8>::from_slice(&[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(ns.len(), 5);
+        let (a, b) = ns.split(2);
+        assert_eq!(a.as_slice(), &[1, 2]);
+        assert_eq!(b.as_slice(), &[3, 4, 5]);
+        assert_eq!(
+            ArrayNumeralString::concat(b, a).as_slice(),
+            &[3, 4, 5, 1, 2]
+        );
+        assert_eq!(ns.rev().as_slice(), &[5, 4, 3, 2, 1]);
+        assert_eq!(ns.num_radix(10), 12345);
+        assert_eq!(
+            ArrayNumeralString::<8>::str_radix(12345, 10, 7).as_slice(),
+            &[0, 0, 1, 2, 3, 4, 5]
+        );
+        assert!(ArrayNumeralString::<4>::from_slice(&[0; 5]).is_none());
+
+        assert_eq!(ArrayNumeralString::<64>::max_len(10), 38);
+        assert_eq!(ArrayNumeralString::<64>::max_len(2), 64);
+        assert_eq!(ArrayNumeralString::<16>::max_len(10), 16);
+    }
+
+    #[test]
+    fn array_ff1() {
+        // NIST SP 800-38G FF1 sample 1.
+        let ff = FF1::<Aes128>::new(
+            &[
+                0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+                0x4F, 0x3C,
+            ],
+            10,
+        )
+        .unwrap();
+        let pt = ArrayNumeralString::from([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let ct = ff.encrypt(&[], &pt).unwrap();
+        assert_eq!(ct.as_slice(), &[2, 4, 3, 3, 4, 7, 7, 4, 8, 4]);
+        assert_eq!(ff.decrypt(&[], &ct).unwrap(), pt);
+
+        // Numeral strings that do not fit in 128 bits are rejected.
+        let pt = ArrayNumeralString::from([0; 39]);
+        assert_eq!(
+            ff.encrypt(&[], &pt).err(),
+            Some(Error::NumeralStringTooLong { len: 39, max: 38 })
+        );
+        let pt = ArrayNumeralString::from([9; 38]);
+        let ct = ff.encrypt(&[], &pt).unwrap();
+        assert_eq!(ff.decrypt(&[], &ct).unwrap(), pt);
+    }
+}
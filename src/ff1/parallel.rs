@@ -0,0 +1,213 @@
+//! FF1 encryption of many numeral strings on the rayon thread pool.
+//!
+//! A single `FF1` is shared by reference between the worker threads, which requires
+//! the block cipher to be `Sync`. Results are collected in the order of the inputs, and
+//! do not depend on how the work was scheduled.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use core::ops::Range;
+use rayon::prelude::*;
+use std::vec::Vec;
+
+use super::{NumeralString, Tweaks, FF1};
+use crate::Error;
+
+/// The number of numeral strings that each task passes to `FF1::encrypt_batch` or
+/// `FF1::decrypt_batch`.
+const CHUNK_LEN: usize = 64;
+
+impl<CIPH> FF1<CIPH>
+where
+    CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone + Sync,
+{
+    /// Encrypts the given numeral strings on the rayon thread pool.
+    ///
+    /// The result is identical to `FF1::encrypt_batch`. If several numeral strings are
+    /// rejected, the error is the one for the first of them.
+    pub fn par_encrypt<NS>(&self, tweaks: Tweaks, xs: &[NS]) -> Result<Vec<NS>, Error>
+    where
+        NS: NumeralString + Send + Sync,
+    {
+        self.par_batch(tweaks, xs, true)
+    }
+
+    /// Decrypts the given numeral strings on the rayon thread pool.
+    ///
+    /// The result is identical to `FF1::decrypt_batch`. If several numeral strings are
+    /// rejected, the error is the one for the first of them.
+    pub fn par_decrypt<NS>(&self, tweaks: Tweaks, xs: &[NS]) -> Result<Vec<NS>, Error>
+    where
+        NS: NumeralString + Send + Sync,
+    {
+        self.par_batch(tweaks, xs, false)
+    }
+
+    /// Encrypts the numeral strings of the given parallel iterator under the same
+    /// tweak, returning the results in the order of the iterator.
+    ///
+    /// If several numeral strings are rejected, the error is the one for the first of
+    /// them.
+    pub fn par_encrypt_iter<NS, I>(&self, tweak: &[u8], xs: I) -> Result<Vec<NS>, Error>
+    where
+        NS: NumeralString + Send,
+        I: IntoParallelIterator<Item = NS>,
+    {
+        first_error(
+            xs.into_par_iter()
+                .map(|x| self.encrypt(tweak, &x))
+                .collect(),
+        )
+    }
+
+    /// Decrypts the numeral strings of the given parallel iterator under the same
+    /// tweak, returning the results in the order of the iterator.
+    ///
+    /// If several numeral strings are rejected, the error is the one for the first of
+    /// them.
+    pub fn par_decrypt_iter<NS, I>(&self, tweak: &[u8], xs: I) -> Result<Vec<NS>, Error>
+    where
+        NS: NumeralString + Send,
+        I: IntoParallelIterator<Item = NS>,
+    {
+        first_error(
+            xs.into_par_iter()
+                .map(|x| self.decrypt(tweak, &x))
+                .collect(),
+        )
+    }
+
+    fn par_batch<NS>(&self, tweaks: Tweaks, xs: &[NS], encrypt: bool) -> Result<Vec<NS>, Error>
+    where
+        NS: NumeralString + Send + Sync,
+    {
+        tweaks.check_len(xs.len())?;
+
+        let chunks: Vec<Result<Vec<NS>, Error>> = xs
+            .par_chunks(CHUNK_LEN)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let start = i * CHUNK_LEN;
+                let tweaks = tweak_range(tweaks, start..start + chunk.len());
+                if encrypt {
+                    self.encrypt_batch(tweaks, chunk)
+                } else {
+                    self.decrypt_batch(tweaks, chunk)
+                }
+            })
+            .collect();
+
+        let mut res = Vec::with_capacity(xs.len());
+        for chunk in chunks {
+            res.extend(chunk?);
+        }
+        Ok(res)
+    }
+}
+
+/// Returns the tweaks of the numeral strings in the given range.
+fn tweak_range<'a>(tweaks: Tweaks<'a>, range: Range<usize>) -> Tweaks<'a> {
+    match tweaks {
+        Tweaks::Shared(tweak) => Tweaks::Shared(tweak),
+        Tweaks::PerItem(tweaks) => Tweaks::PerItem(&tweaks[range]),
+    }
+}
+
+/// Returns the results, or the first error among them.
+///
+/// Collecting a parallel iterator directly into a `Result` returns whichever error was
+/// found first in time, which depends on scheduling.
+fn first_error<NS>(results: Vec<Result<NS, Error>>) -> Result<Vec<NS>, Error> {
+    results.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes256;
+    use rayon::prelude::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::ff1::batch::tests::numeral_strings;
+    use crate::ff1::{FlexibleNumeralString, NumeralString, Tweaks, FF1};
+    use crate::Error;
+
+    /// Returns `count` decimal numeral strings with lengths from 6 to 35.
+    fn decimal_strings(count: usize) -> Vec<FlexibleNumeralString> {
+        let lens: Vec<usize> = (0..count).map(|i| 6 + i % 30).collect();
+        numeral_strings(10, &lens)
+    }
+
+    #[test]
+    fn matches_sequential() {
+        let ff = FF1::<Aes256>::new(&[1; 32], 10).unwrap();
+        let xs = decimal_strings(1000);
+        let tweaks: Vec<Vec<u8>> = (0..xs.len()).map(|i| vec![i as u8; i % 7]).collect();
+        let tweaks: Vec<&[u8]> = tweaks.iter().map(|t| &t[..]).collect();
+
+        for &tweaks in [Tweaks::Shared(b"tweak"), Tweaks::PerItem(&tweaks)].iter() {
+            let expected = ff.encrypt_batch(tweaks, &xs).unwrap();
+            let cts = ff.par_encrypt(tweaks, &xs).unwrap();
+            assert_eq!(cts.len(), xs.len());
+            for (ct, expected) in cts.iter().zip(&expected) {
+                assert_eq!(ct.num_radix(10), expected.num_radix(10));
+                assert_eq!(ct.len(), expected.len());
+            }
+
+            let pts = ff.par_decrypt(tweaks, &cts).unwrap();
+            for (pt, x) in pts.iter().zip(&xs) {
+                assert_eq!(pt.num_radix(10), x.num_radix(10));
+            }
+        }
+
+        let cts = ff
+            .par_encrypt_iter(b"tweak", decimal_strings(1000))
+            .unwrap();
+        let pts = ff.par_decrypt_iter(b"tweak", cts).unwrap();
+        for (pt, x) in pts.iter().zip(&xs) {
+            assert_eq!(pt.num_radix(10), x.num_radix(10));
+        }
+
+        // Indexed parallel iterators keep their order.
+        let cts = ff
+            .par_encrypt_iter(
+                &[],
+                (0..1000usize)
+                    .into_par_iter()
+                    .map(|i| FlexibleNumeralString::from(vec![(i % 10) as u16; 6])),
+            )
+            .unwrap();
+        for (i, ct) in cts.iter().enumerate() {
+            let x = FlexibleNumeralString::from(vec![(i % 10) as u16; 6]);
+            assert_eq!(ct.num_radix(10), ff.encrypt(&[], &x).unwrap().num_radix(10));
+        }
+    }
+
+    #[test]
+    fn deterministic_errors() {
+        let ff = FF1::<Aes256>::new(&[1; 32], 10).unwrap();
+        let mut xs = decimal_strings(1000);
+        xs[700] = FlexibleNumeralString::from(vec![0; 5]);
+        xs[900] = FlexibleNumeralString::from(vec![10; 6]);
+        xs[300] = FlexibleNumeralString::from(vec![1, 2, 3, 4, 5, 11]);
+
+        let expected = Some(Error::InvalidNumeral {
+            position: 5,
+            value: 11,
+            radix: 10,
+        });
+        for _ in 0..10 {
+            assert_eq!(ff.par_encrypt(Tweaks::Shared(&[]), &xs).err(), expected);
+        }
+        assert_eq!(ff.par_decrypt_iter(&[], xs).err(), expected);
+
+        assert_eq!(
+            ff.par_encrypt(Tweaks::PerItem(&[&[]]), &decimal_strings(2))
+                .err(),
+            Some(Error::LengthOutOfBounds {
+                len: 1,
+                min: 2,
+                max: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! A Rust implementation of the original FF3 algorithm, specified in
+//! [NIST Special Publication 800-38G](http://dx.doi.org/10.6028/NIST.SP.800-38G).
+//!
+//! FF3 is no longer approved: [Durak and Vaudenay](https://eprint.iacr.org/2017/521)
+//! showed a practical attack against its 64-bit tweak, and NIST replaced it with
+//! [`FF3-1`](crate::ff3_1). This module is only provided so that data encrypted with
+//! FF3 can be decrypted and re-encrypted with an approved algorithm. It is gated
+//! behind the `legacy-ff3` feature.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+
+use crate::ff1::{DomainPolicy, NumeralString, Radix};
+use crate::ff3_1::{decrypt_with_tweak, encrypt_with_tweak, reversed_key_cipher};
+use crate::Error;
+
+/// A struct for performing FF3 encryption and decryption operations.
+pub struct FF3<CIPH: BlockCipher<BlockSize = U16>> {
+    ciph: CIPH,
+    radix: Radix,
+}
+
+/// Splits the 64-bit tweak into T_L = T[0..31] and T_R = T[32..63].
+fn split_tweak(tweak: &[u8; 8]) -> ([u8; 4], [u8; 4]) {
+    (
+        [tweak[0], tweak[1], tweak[2], tweak[3]],
+        [tweak[4], tweak[5], tweak[6], tweak[7]],
+    )
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> FF3<CIPH> {
+    /// Creates a new FF3 object for the given key and radix.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the given radix is not in [2..2^16].
+    pub fn new(key: &[u8], radix: u32) -> Result<Self, Error> {
+        let ciph = reversed_key_cipher(key)?;
+        let radix = Radix::from(radix)?;
+        Ok(FF3 { ciph, radix })
+    }
+
+    /// Encrypts the given numeral string with the given 64-bit tweak.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or its
+    /// length is not in [2..2 * floor(log_radix(2^96))].
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8; 8], x: &NS) -> Result<NS, Error> {
+        // 3. Let T_L = T[0..31] and T_R = T[32..63].
+        let (t_l, t_r) = split_tweak(tweak);
+        encrypt_with_tweak(&self.ciph, &self.radix, DomainPolicy::Legacy, &t_l, &t_r, x)
+    }
+
+    /// Decrypts the given numeral string with the given 64-bit tweak.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or its
+    /// length is not in [2..2 * floor(log_radix(2^96))].
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8; 8], x: &NS) -> Result<NS, Error> {
+        // 3. Let T_L = T[0..31] and T_R = T[32..63].
+        let (t_l, t_r) = split_tweak(tweak);
+        decrypt_with_tweak(&self.ciph, &self.radix, DomainPolicy::Legacy, &t_l, &t_r, x)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use aes::{Aes128, Aes192, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::FF3;
+    use crate::ff1::FlexibleNumeralString;
+
+    #[test]
+    fn test_vectors() {
+        enum AesType {
+            AES128,
+            AES192,
+            AES256,
+        };
+
+        struct TestVector {
+            aes: AesType,
+            key: Vec<u8>,
+            radix: u32,
+            tweak: [u8; 8],
+            pt: Vec<u16>,
+            ct: Vec<u16>,
+        };
+
+        let test_vectors = vec![
+            // From https://csrc.nist.gov/CSRC/media/Projects/Cryptographic-Standards-and-Guidelines/documents/examples/FF3samples.pdf
+            TestVector {
+                // Sample #1
+                aes: AesType::AES128,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![7, 5, 0, 9, 1, 8, 8, 1, 4, 0, 5, 8, 6, 5, 4, 6, 0, 7],
+            },
+            TestVector {
+                // Sample #2
+                aes: AesType::AES128,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94,
+                ],
+                radix: 10,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![0, 1, 8, 9, 8, 9, 8, 3, 9, 1, 8, 9, 3, 9, 5, 3, 8, 4],
+            },
+            TestVector {
+                // Sample #3
+                aes: AesType::AES128,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    4, 8, 5, 9, 8, 3, 6, 7, 1, 6, 2, 2, 5, 2, 5, 6, 9, 6, 2, 9, 3, 9, 7, 4, 1, 6,
+                    2, 2, 6,
+                ],
+            },
+            TestVector {
+                // Sample #4
+                aes: AesType::AES128,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94,
+                ],
+                radix: 10,
+                tweak: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    3, 4, 6, 9, 5, 2, 2, 4, 8, 2, 1, 7, 3, 4, 5, 3, 5, 1, 2, 2, 6, 1, 3, 7, 0, 1,
+                    4, 3, 4,
+                ],
+            },
+            TestVector {
+                // Sample #5
+                aes: AesType::AES128,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94,
+                ],
+                radix: 26,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+                ],
+                ct: vec![
+                    16, 2, 25, 20, 4, 0, 18, 9, 9, 2, 15, 23, 2, 0, 12, 19, 10, 20, 11,
+                ],
+            },
+            TestVector {
+                // Sample #6
+                aes: AesType::AES192,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![6, 4, 6, 9, 6, 5, 3, 9, 3, 8, 7, 5, 0, 2, 8, 7, 5, 5],
+            },
+            TestVector {
+                // Sample #7
+                aes: AesType::AES192,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+                ],
+                radix: 10,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![9, 6, 1, 6, 1, 0, 5, 1, 4, 4, 9, 1, 4, 2, 4, 4, 4, 6],
+            },
+            TestVector {
+                // Sample #8
+                aes: AesType::AES192,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    5, 3, 0, 4, 8, 8, 8, 4, 0, 6, 5, 3, 5, 0, 2, 0, 4, 5, 4, 1, 7, 8, 6, 3, 8, 0,
+                    8, 0, 7,
+                ],
+            },
+            TestVector {
+                // Sample #9
+                aes: AesType::AES192,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+                ],
+                radix: 10,
+                tweak: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    9, 8, 0, 8, 3, 8, 0, 2, 6, 7, 8, 8, 2, 0, 3, 8, 9, 2, 9, 5, 0, 4, 1, 4, 8, 3,
+                    5, 1, 2,
+                ],
+            },
+            TestVector {
+                // Sample #10
+                aes: AesType::AES192,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6,
+                ],
+                radix: 26,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+                ],
+                ct: vec![
+                    18, 0, 18, 17, 14, 2, 19, 15, 19, 7, 10, 9, 24, 25, 15, 9, 25, 8, 8,
+                ],
+            },
+            TestVector {
+                // Sample #11
+                aes: AesType::AES256,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7,
+                    0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![9, 2, 2, 0, 1, 1, 2, 0, 5, 5, 6, 2, 7, 7, 7, 4, 9, 5],
+            },
+            TestVector {
+                // Sample #12
+                aes: AesType::AES256,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7,
+                    0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+                ],
+                radix: 10,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0],
+                ct: vec![5, 0, 4, 1, 4, 9, 8, 6, 5, 5, 7, 8, 0, 5, 6, 1, 4, 0],
+            },
+            TestVector {
+                // Sample #13
+                aes: AesType::AES256,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7,
+                    0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+                ],
+                radix: 10,
+                tweak: [0xD8, 0xE7, 0x92, 0x0A, 0xFA, 0x33, 0x0A, 0x73],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    0, 4, 3, 4, 4, 3, 4, 3, 2, 3, 5, 7, 9, 2, 5, 9, 9, 1, 6, 5, 7, 3, 4, 6, 2, 2,
+                    6, 9, 9,
+                ],
+            },
+            TestVector {
+                // Sample #14
+                aes: AesType::AES256,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7,
+                    0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+                ],
+                radix: 10,
+                tweak: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                pt: vec![
+                    8, 9, 0, 1, 2, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 0, 0, 0, 0, 0, 7, 8, 9, 0, 0, 0,
+                    0, 0, 0,
+                ],
+                ct: vec![
+                    3, 0, 8, 5, 9, 2, 3, 9, 9, 9, 9, 3, 7, 4, 0, 5, 3, 8, 7, 2, 3, 6, 5, 5, 5, 5,
+                    8, 2, 2,
+                ],
+            },
+            TestVector {
+                // Sample #15
+                aes: AesType::AES256,
+                key: vec![
+                    0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04,
+                    0xFC, 0x6A, 0x94, 0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7,
+                    0x15, 0x88, 0x09, 0xCF, 0x4F, 0x3C,
+                ],
+                radix: 26,
+                tweak: [0x9A, 0x76, 0x8A, 0x92, 0xF6, 0x0E, 0x12, 0xD8],
+                pt: vec![
+                    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+                ],
+                ct: vec![
+                    25, 0, 11, 2, 16, 24, 13, 15, 19, 10, 9, 11, 17, 11, 7, 11, 20, 3, 8,
+                ],
+            },
+        ];
+
+        for tv in test_vectors {
+            let (ct, pt) = match tv.aes {
+                AesType::AES128 => {
+                    let ff = FF3::<Aes128>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+                AesType::AES192 => {
+                    let ff = FF3::<Aes192>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+                AesType::AES256 => {
+                    let ff = FF3::<Aes256>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+            };
+            assert_eq!(Vec::from(ct.unwrap()), tv.ct);
+            assert_eq!(Vec::from(pt.unwrap()), tv.pt);
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! A Rust implementation of the FF3-1 algorithm, specified in
+//! [NIST Special Publication 800-38G Revision 1](https://doi.org/10.6028/NIST.SP.800-38Gr1-draft).
+
+use aes::block_cipher::{generic_array::typenum::U16, Block, BlockCipher, NewBlockCipher};
+use core::cmp;
+
+use crate::ff1::{check_numerals, key_from_slice, DomainPolicy, Numeral, NumeralString, Radix};
+use crate::Error;
+
+/// Calculates maxlen = 2 * floor(log_radix(2^96)).
+fn max_len(radix: &Radix) -> usize {
+    let radix = u128::from(radix.to_u32());
+    let mut len = 0;
+    let mut acc = 1u128;
+    while acc * radix <= 1 << 96 {
+        acc *= radix;
+        len += 1;
+    }
+    2 * len
+}
+
+/// Returns minlen under the given domain policy.
+fn min_len(radix: &Radix, policy: DomainPolicy) -> usize {
+    match policy {
+        DomainPolicy::Strict => cmp::max(radix.min_len(), 2),
+        DomainPolicy::Legacy => 2,
+    }
+}
+
+/// Checks that the length n is in [minlen..maxlen].
+fn check_length(radix: &Radix, policy: DomainPolicy, n: usize) -> Result<(), Error> {
+    let (min, max) = (min_len(radix, policy), max_len(radix));
+    if n < min && policy == DomainPolicy::Strict {
+        return Err(Error::DomainTooSmall);
+    }
+    if n < min || n > max {
+        return Err(Error::LengthOutOfBounds { len: n, min, max });
+    }
+    Ok(())
+}
+
+/// Computes y = NUM(REVB(CIPH_REVB(K)(REVB(P)))) for
+/// P = W ⊕ [i]^4 || [NUM_radix(REV(X))]^12.
+fn round<CIPH: BlockCipher<BlockSize = U16>, NS: NumeralString>(
+    ciph: &CIPH,
+    radix: u32,
+    w: &[u8; 4],
+    i: u8,
+    x: &NS,
+) -> NS::Num {
+    // ii. Let P = W ⊕ [i]^4 || [NUM_radix(REV(X))]^12.
+    let mut p = [0; 16];
+    p[..4].copy_from_slice(w);
+    p[3] ^= i;
+    p[4..].copy_from_slice(x.rev().num_radix(radix).to_bytes(12).as_ref());
+
+    // iii. Let S = REVB(CIPH_REVB(K)(REVB(P))).
+    let mut block = Block::<CIPH>::default();
+    for (b, p) in block.iter_mut().zip(p.iter().rev()) {
+        *b = *p;
+    }
+    ciph.encrypt_block(&mut block);
+
+    // iv. Let y = NUM(S).
+    NS::Num::from_bytes(block.into_iter().rev())
+}
+
+/// Encrypts the given numeral string with the tweak halves T_L and T_R.
+///
+/// This is steps 1, 2 and 4 of FF3-1.Encrypt, which are shared with the original FF3.
+pub(crate) fn encrypt_with_tweak<CIPH: BlockCipher<BlockSize = U16>, NS: NumeralString>(
+    ciph: &CIPH,
+    radix: &Radix,
+    policy: DomainPolicy,
+    t_l: &[u8; 4],
+    t_r: &[u8; 4],
+    x: &NS,
+) -> Result<NS, Error> {
+    check_numerals(x, radix.to_u32())?;
+    check_length(radix, policy, x.len())?;
+
+    let n = x.len();
+
+    // 1. Let u = ceil(n / 2); v = n - u.
+    let u = (n + 1) / 2;
+    let v = n - u;
+
+    // 2. Let A = X[1..u]; B = X[u + 1..n].
+    let (mut x_a, mut x_b) = x.split(u);
+
+    for i in 0..8 {
+        // 4i. If i is even, let m = u and W = T_R; else, let m = v and W = T_L.
+        let (m, w) = if i % 2 == 0 { (u, t_r) } else { (v, t_l) };
+
+        // 4ii - 4iv. Let P = W ⊕ [i]^4 || [NUM_radix(REV(B))]^12; y = NUM(S).
+        let y = round(ciph, radix.to_u32(), w, i, &x_b);
+
+        // 4v. Let c = (NUM_radix(REV(A)) + y) mod radix^m.
+        let c = x_a
+            .rev()
+            .num_radix(radix.to_u32())
+            .add_mod_exp(y, radix.to_u32(), m);
+
+        // 4vi. Let C = REV(STR^m_radix(c)).
+        let x_c = NS::str_radix(c, radix.to_u32(), m).rev();
+
+        // 4vii. Let A = B.
+        x_a = x_b;
+
+        // 4viii. Let B = C.
+        x_b = x_c;
+    }
+
+    // 5. Return A || B.
+    Ok(NS::concat(x_a, x_b))
+}
+
+/// Decrypts the given numeral string with the tweak halves T_L and T_R.
+///
+/// This is steps 1, 2 and 4 of FF3-1.Decrypt, which are shared with the original FF3.
+pub(crate) fn decrypt_with_tweak<CIPH: BlockCipher<BlockSize = U16>, NS: NumeralString>(
+    ciph: &CIPH,
+    radix: &Radix,
+    policy: DomainPolicy,
+    t_l: &[u8; 4],
+    t_r: &[u8; 4],
+    x: &NS,
+) -> Result<NS, Error> {
+    check_numerals(x, radix.to_u32())?;
+    check_length(radix, policy, x.len())?;
+
+    let n = x.len();
+
+    // 1. Let u = ceil(n / 2); v = n - u.
+    let u = (n + 1) / 2;
+    let v = n - u;
+
+    // 2. Let A = X[1..u]; B = X[u + 1..n].
+    let (mut x_a, mut x_b) = x.split(u);
+
+    for i in 0..8 {
+        let i = 7 - i;
+
+        // 4i. If i is even, let m = u and W = T_R; else, let m = v and W = T_L.
+        let (m, w) = if i % 2 == 0 { (u, t_r) } else { (v, t_l) };
+
+        // 4ii - 4iv. Let P = W ⊕ [i]^4 || [NUM_radix(REV(A))]^12; y = NUM(S).
+        let y = round(ciph, radix.to_u32(), w, i, &x_a);
+
+        // 4v. Let c = (NUM_radix(REV(B)) - y) mod radix^m.
+        let c = x_b
+            .rev()
+            .num_radix(radix.to_u32())
+            .sub_mod_exp(y, radix.to_u32(), m);
+
+        // 4vi. Let C = REV(STR^m_radix(c)).
+        let x_c = NS::str_radix(c, radix.to_u32(), m).rev();
+
+        // 4vii. Let B = A.
+        x_b = x_a;
+
+        // 4viii. Let A = C.
+        x_a = x_c;
+    }
+
+    // 5. Return A || B.
+    Ok(NS::concat(x_a, x_b))
+}
+
+/// Returns the block cipher keyed with REVB(K), as used by FF3 and FF3-1.
+pub(crate) fn reversed_key_cipher<CIPH: NewBlockCipher>(key: &[u8]) -> Result<CIPH, Error> {
+    let mut key = key_from_slice::<CIPH>(key)?.clone();
+    key.reverse();
+    Ok(CIPH::new(&key))
+}
+
+/// A struct for performing FF3-1 encryption and decryption operations.
+#[allow(non_camel_case_types)]
+pub struct FF3_1<CIPH: BlockCipher<BlockSize = U16>> {
+    ciph: CIPH,
+    radix: Radix,
+    policy: DomainPolicy,
+}
+
+/// Splits the 56-bit tweak into T_L = T[0..27] || 0^4 and T_R = T[32..55] || T[28..31] || 0^4.
+fn split_tweak(tweak: &[u8; 7]) -> ([u8; 4], [u8; 4]) {
+    (
+        [tweak[0], tweak[1], tweak[2], tweak[3] & 0xf0],
+        [tweak[4], tweak[5], tweak[6], tweak[3] << 4],
+    )
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> FF3_1<CIPH> {
+    /// Creates a new FF3-1 object for the given key and radix.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the given radix is not in [2..2^16].
+    pub fn new(key: &[u8], radix: u32) -> Result<Self, Error> {
+        let ciph = reversed_key_cipher(key)?;
+        let radix = Radix::from(radix)?;
+        Ok(FF3_1 {
+            ciph,
+            radix,
+            policy: DomainPolicy::default(),
+        })
+    }
+
+    /// Sets the policy for the minimum length of numeral strings.
+    pub fn with_domain_policy(mut self, policy: DomainPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the policy for the minimum length of numeral strings.
+    pub fn domain_policy(&self) -> DomainPolicy {
+        self.policy
+    }
+
+    /// Returns minlen, the minimum length of numeral strings under the domain policy.
+    pub fn min_len(&self) -> usize {
+        min_len(&self.radix, self.policy)
+    }
+
+    /// Returns maxlen = 2 * floor(log_radix(2^96)), the maximum length of numeral strings.
+    pub fn max_len(&self) -> usize {
+        max_len(&self.radix)
+    }
+
+    /// Encrypts the given numeral string with the given 56-bit tweak.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or its
+    /// length is not in [minlen..maxlen].
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8; 7], x: &NS) -> Result<NS, Error> {
+        // 3. Let T_L = T[0..27] || 0^4 and T_R = T[32..55] || T[28..31] || 0^4.
+        let (t_l, t_r) = split_tweak(tweak);
+        encrypt_with_tweak(&self.ciph, &self.radix, self.policy, &t_l, &t_r, x)
+    }
+
+    /// Decrypts the given numeral string with the given 56-bit tweak.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or its
+    /// length is not in [minlen..maxlen].
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8; 7], x: &NS) -> Result<NS, Error> {
+        // 3. Let T_L = T[0..27] || 0^4 and T_R = T[32..55] || T[28..31] || 0^4.
+        let (t_l, t_r) = split_tweak(tweak);
+        decrypt_with_tweak(&self.ciph, &self.radix, self.policy, &t_l, &t_r, x)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use aes::{Aes128, Aes192, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::FF3_1;
+    use crate::ff1::{AutoNumeral, DomainPolicy, FlexibleNumeralString, NumeralString};
+    use crate::Error;
+
+    /// A numeral string that relies on the default implementations of `rev` and
+    /// `invalid_numeral`.
+    struct Numerals(FlexibleNumeralString);
+
+    impl NumeralString for Numerals {
+        type Num = AutoNumeral;
+
+        fn is_valid(&self, radix: u32) -> bool {
+            self.0.is_valid(radix)
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn split(&self, u: usize) -> (Self, Self) {
+            let (a, b) = self.0.split(u);
+            (Numerals(a), Numerals(b))
+        }
+
+        fn concat(a: Self, b: Self) -> Self {
+            Numerals(FlexibleNumeralString::concat(a.0, b.0))
+        }
+
+        fn num_radix(&self, radix: u32) -> AutoNumeral {
+            self.0.num_radix(radix)
+        }
+
+        fn str_radix(x: AutoNumeral, radix: u32, m: usize) -> Self {
+            Numerals(FlexibleNumeralString::str_radix(x, radix, m))
+        }
+    }
+
+    #[test]
+    fn test_vectors() {
+        enum AesType {
+            AES128,
+            AES192,
+            AES256,
+        };
+
+        struct TestVector {
+            aes: AesType,
+            key: Vec<u8>,
+            radix: u32,
+            tweak: [u8; 7],
+            pt: Vec<u16>,
+            ct: Vec<u16>,
+        };
+
+        let test_vectors = vec![
+            // From the NIST ACVP AES-FF3-1 sample vectors
+            TestVector {
+                aes: AesType::AES128,
+                key: vec![
+                    0x2D, 0xE7, 0x9D, 0x23, 0x2D, 0xF5, 0x58, 0x5D, 0x68, 0xCE, 0x47, 0x88, 0x2A,
+                    0xE2, 0x56, 0xD6,
+                ],
+                radix: 10,
+                tweak: [0xCB, 0xD0, 0x92, 0x80, 0x97, 0x95, 0x64],
+                pt: vec![3, 9, 9, 2, 5, 2, 0, 2, 4, 0],
+                ct: vec![8, 9, 0, 1, 8, 0, 1, 1, 0, 6],
+            },
+            TestVector {
+                aes: AesType::AES128,
+                key: vec![
+                    0x01, 0xC6, 0x30, 0x17, 0x11, 0x14, 0x38, 0xF7, 0xFC, 0x8E, 0x24, 0xEB, 0x16,
+                    0xC7, 0x1A, 0xB5,
+                ],
+                radix: 10,
+                tweak: [0xC4, 0xE8, 0x22, 0xDC, 0xD0, 0x9F, 0x27],
+                pt: vec![
+                    6, 0, 7, 6, 1, 7, 5, 7, 4, 6, 3, 1, 1, 6, 8, 6, 9, 3, 1, 8, 4, 3, 7, 6, 5, 8,
+                    0, 4, 2, 2, 9, 7, 3, 0, 5, 9, 3, 4, 9, 1, 4, 8, 2, 4, 4, 5, 7, 4, 8, 4, 5, 3,
+                    8, 5, 6, 2,
+                ],
+                ct: vec![
+                    3, 5, 6, 3, 7, 1, 4, 4, 0, 9, 2, 4, 7, 3, 8, 3, 8, 8, 9, 2, 7, 9, 6, 7, 0, 2,
+                    7, 3, 9, 6, 2, 8, 3, 9, 4, 3, 7, 6, 9, 1, 5, 1, 7, 7, 4, 4, 8, 2, 9, 0, 8, 4,
+                    7, 2, 9, 3,
+                ],
+            },
+            TestVector {
+                // wfmwlrorcd -> ywowehycyd
+                aes: AesType::AES128,
+                key: vec![
+                    0x71, 0x83, 0x85, 0xE6, 0x54, 0x25, 0x34, 0x60, 0x44, 0x19, 0xE8, 0x3C, 0xE3,
+                    0x87, 0xA4, 0x37,
+                ],
+                radix: 26,
+                tweak: [0xB6, 0xF3, 0x50, 0x84, 0xFA, 0x90, 0xE1],
+                pt: vec![22, 5, 12, 22, 11, 17, 14, 17, 2, 3],
+                ct: vec![24, 22, 14, 22, 4, 7, 24, 2, 24, 3],
+            },
+            TestVector {
+                // kkuomenbzqvggfbteqdyanwpmhzdmoicekiihkrm -> belcfahcwwytwrckieymthabgjjfkxtxauipmjja
+                aes: AesType::AES128,
+                key: vec![
+                    0xDB, 0x60, 0x2D, 0xFF, 0x22, 0xED, 0x7E, 0x84, 0xC8, 0xD8, 0xC8, 0x65, 0xA9,
+                    0x41, 0xA2, 0x38,
+                ],
+                radix: 26,
+                tweak: [0xEB, 0xEF, 0xD6, 0x3B, 0xCC, 0x20, 0x83],
+                pt: vec![
+                    10, 10, 20, 14, 12, 4, 13, 1, 25, 16, 21, 6, 6, 5, 1, 19, 4, 16, 3, 24, 0, 13,
+                    22, 15, 12, 7, 25, 3, 12, 14, 8, 2, 4, 10, 8, 8, 7, 10, 17, 12,
+                ],
+                ct: vec![
+                    1, 4, 11, 2, 5, 0, 7, 2, 22, 22, 24, 19, 22, 17, 2, 10, 8, 4, 24, 12, 19, 7, 0,
+                    1, 6, 9, 9, 5, 10, 23, 19, 23, 0, 20, 8, 15, 12, 9, 9, 0,
+                ],
+            },
+            TestVector {
+                aes: AesType::AES192,
+                key: vec![
+                    0xF6, 0x2E, 0xDB, 0x77, 0x7A, 0x67, 0x10, 0x75, 0xD4, 0x75, 0x63, 0xF3, 0xA1,
+                    0xE9, 0xAC, 0x79, 0x7A, 0xA7, 0x06, 0xA2, 0xD8, 0xE0, 0x2F, 0xC8,
+                ],
+                radix: 10,
+                tweak: [0x49, 0x3B, 0x84, 0x51, 0xBF, 0x67, 0x16],
+                pt: vec![4, 4, 0, 6, 6, 1, 6, 8, 0, 8],
+                ct: vec![1, 8, 0, 7, 7, 4, 4, 7, 6, 2],
+            },
+            TestVector {
+                aes: AesType::AES256,
+                key: vec![
+                    0x1F, 0xAA, 0x03, 0xEF, 0xF5, 0x5A, 0x06, 0xF8, 0xFA, 0xB3, 0xF1, 0xDC, 0x57,
+                    0x12, 0x7D, 0x49, 0x3E, 0x2F, 0x8F, 0x5C, 0x36, 0x55, 0x40, 0x46, 0x7A, 0x3A,
+                    0x05, 0x5B, 0xDB, 0xE6, 0x48, 0x1D,
+                ],
+                radix: 10,
+                tweak: [0x4D, 0x67, 0x13, 0x0C, 0x03, 0x04, 0x45],
+                pt: vec![3, 6, 7, 9, 4, 0, 9, 4, 3, 6],
+                ct: vec![1, 7, 3, 5, 7, 9, 4, 8, 5, 9],
+            },
+        ];
+
+        for tv in test_vectors {
+            let (ct, pt) = match tv.aes {
+                AesType::AES128 => {
+                    let ff = FF3_1::<Aes128>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+                AesType::AES192 => {
+                    let ff = FF3_1::<Aes192>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+                AesType::AES256 => {
+                    let ff = FF3_1::<Aes256>::new(&tv.key, tv.radix).unwrap();
+                    (
+                        ff.encrypt(&tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                        ff.decrypt(&tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                    )
+                }
+            };
+            assert_eq!(Vec::from(ct.unwrap()), tv.ct);
+            assert_eq!(Vec::from(pt.unwrap()), tv.pt);
+        }
+    }
+
+    #[test]
+    fn length_bounds() {
+        let ff = FF3_1::<Aes128>::new(&[0; 16], 10).unwrap();
+        let tweak = [0; 7];
+
+        // minlen = 6 as 10^6 >= 1,000,000; maxlen = 2 * floor(log_10(2^96)) = 56
+        assert_eq!(ff.domain_policy(), DomainPolicy::Strict);
+        assert_eq!(ff.min_len(), 6);
+        assert_eq!(ff.max_len(), 56);
+        for &n in [1, 2, 5].iter() {
+            let x = FlexibleNumeralString::from(vec![0; n]);
+            assert_eq!(ff.encrypt(&tweak, &x).err(), Some(Error::DomainTooSmall));
+            assert_eq!(ff.decrypt(&tweak, &x).err(), Some(Error::DomainTooSmall));
+        }
+        assert!(ff
+            .encrypt(&tweak, &FlexibleNumeralString::from(vec![0; 6]))
+            .is_ok());
+        assert!(ff
+            .encrypt(&tweak, &FlexibleNumeralString::from(vec![0; 56]))
+            .is_ok());
+        assert_eq!(
+            ff.encrypt(&tweak, &FlexibleNumeralString::from(vec![0; 57]))
+                .err(),
+            Some(Error::LengthOutOfBounds {
+                len: 57,
+                min: 6,
+                max: 56
+            })
+        );
+        assert!(ff
+            .decrypt(&tweak, &FlexibleNumeralString::from(vec![0; 57]))
+            .is_err());
+
+        // The legacy policy only requires a length of at least 2.
+        let ff = ff.with_domain_policy(DomainPolicy::Legacy);
+        assert_eq!(ff.min_len(), 2);
+        assert_eq!(
+            ff.encrypt(&tweak, &FlexibleNumeralString::from(vec![0; 1]))
+                .err(),
+            Some(Error::LengthOutOfBounds {
+                len: 1,
+                min: 2,
+                max: 56
+            })
+        );
+        assert!(ff
+            .encrypt(&tweak, &FlexibleNumeralString::from(vec![0; 2]))
+            .is_ok());
+    }
+
+    #[test]
+    fn default_rev() {
+        let ns = Numerals(FlexibleNumeralString::from(vec![1, 2, 3, 4, 5]));
+        assert_eq!(Vec::from(ns.rev().0), vec![5, 4, 3, 2, 1]);
+        assert_eq!(ns.split(0).0.rev().len(), 0);
+
+        let ff = FF3_1::<Aes128>::new(&[7; 16], 10).unwrap();
+        let tweak = [1, 2, 3, 4, 5, 6, 7];
+        let pt: Vec<u16> = (0..19).map(|i| i % 10).collect();
+        let expected = ff
+            .encrypt(&tweak, &FlexibleNumeralString::from(pt.clone()))
+            .unwrap();
+        let ct = ff
+            .encrypt(&tweak, &Numerals(FlexibleNumeralString::from(pt.clone())))
+            .unwrap();
+        let ct = Vec::from(ct.0);
+        assert_eq!(ct, Vec::from(expected));
+        let res = ff
+            .decrypt(&tweak, &Numerals(FlexibleNumeralString::from(ct)))
+            .unwrap();
+        assert_eq!(Vec::from(res.0), pt);
+    }
+
+    #[test]
+    fn default_invalid_numeral() {
+        let valid: Vec<u16> = (0..20).map(|i| i % 10).collect();
+        assert_eq!(
+            Numerals(FlexibleNumeralString::from(valid.clone())).invalid_numeral(10),
+            None
+        );
+        for &(position, value) in &[(0, 10), (7, 11), (19, 65535)] {
+            let mut numerals = valid.clone();
+            numerals[position] = value;
+            let ns = Numerals(FlexibleNumeralString::from(numerals.clone()));
+            assert_eq!(ns.invalid_numeral(10), Some((position, u32::from(value))));
+            assert_eq!(
+                ns.invalid_numeral(10),
+                ns.0.invalid_numeral(10),
+                "default disagrees with the override"
+            );
+        }
+
+        let ff = FF3_1::<Aes128>::new(&[7; 16], 10).unwrap();
+        assert_eq!(
+            ff.encrypt(
+                &[0; 7],
+                &Numerals(FlexibleNumeralString::from(vec![10; 20]))
+            )
+            .err(),
+            Some(Error::InvalidNumeral {
+                position: 0,
+                value: 10,
+                radix: 10
+            })
+        );
+    }
+}
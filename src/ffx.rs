@@ -0,0 +1,306 @@
+//! A generic engine for FFX-style alternating Feistel networks.
+//!
+//! FFX ([Bellare, Rogaway and Spies](https://csrc.nist.gov/CSRC/media/Projects/Block-Cipher-Techniques/documents/BCM/proposed-modes/ffx/ffx-spec.pdf))
+//! describes a family of format-preserving Feistel ciphers, parameterized by the
+//! radix, the split of the input into two halves, the number of rounds, the round
+//! function and the way the round function output is added to a half. FF1 is one
+//! member of this family. This module implements the Feistel network itself (FFX
+//! method 2), along with the A2 and A10 parameter collections from the FFX addendum.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use core::convert::TryInto;
+
+use crate::ff1::{check_numerals, Numeral, NumeralString, Prf};
+use crate::Error;
+
+mod a10;
+mod a2;
+pub use a10::FFXA10;
+pub use a2::FFXA2;
+
+/// How the output of the round function is added to a half of the numeral string.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Addition {
+    /// Addition modulo radix^m of the numbers that the half and the round function
+    /// output represent.
+    Blockwise,
+    /// Numeral-by-numeral addition modulo radix. Only supported for radix 2, where it
+    /// is exclusive-or.
+    Characterwise,
+}
+
+/// The parameters of an alternating Feistel network.
+pub trait Feistel {
+    /// The state of the round function that only depends on the length and tweak.
+    type Context;
+
+    /// Returns the radix of the numeral strings.
+    fn radix(&self) -> u32;
+
+    /// Returns split(n), the length of the first half of a numeral string of length n.
+    fn split(&self, n: usize) -> usize;
+
+    /// Returns the number of rounds for a numeral string of length n.
+    fn rounds(&self, n: usize) -> u8;
+
+    /// Returns how the round function output is added to a half.
+    fn addition(&self) -> Addition;
+
+    /// Returns the round function state for numeral strings of length n and the given
+    /// tweak.
+    fn context(&self, n: usize, tweak: &[u8]) -> Self::Context;
+
+    /// Returns the output of the round function for round i, given the half B.
+    ///
+    /// The output must be less than radix^m, where m is the length of the half that it
+    /// is added to, unless the addition is blockwise.
+    fn round<NS: NumeralString>(&self, context: &Self::Context, n: usize, i: u8, b: &NS)
+        -> NS::Num;
+}
+
+/// The lengths u and v of the two halves of numeral strings of length n, with the
+/// moduli radix^u and radix^v.
+pub(crate) struct Halves<N> {
+    u: usize,
+    v: usize,
+    modulus_u: N,
+    modulus_v: N,
+}
+
+impl<N: Numeral> Halves<N> {
+    pub(crate) fn new<F: Feistel>(f: &F, n: usize) -> Self {
+        // Let u = split(n); v = n - u.
+        let u = f.split(n);
+        let v = n - u;
+        Halves {
+            u,
+            v,
+            modulus_u: N::exp(f.radix(), u),
+            modulus_v: N::exp(f.radix(), v),
+        }
+    }
+
+    /// Returns n, the length of the numeral strings.
+    pub(crate) fn len(&self) -> usize {
+        self.u + self.v
+    }
+
+    /// Returns m and radix^m for round i, where m = u if i is even, and m = v otherwise.
+    pub(crate) fn half(&self, i: u8) -> (usize, &N) {
+        if i % 2 == 0 {
+            (self.u, &self.modulus_u)
+        } else {
+            (self.v, &self.modulus_v)
+        }
+    }
+}
+
+/// Computes A ⊞ y for a half of length m.
+fn add<F: Feistel, NS: NumeralString>(
+    f: &F,
+    a: &NS,
+    y: NS::Num,
+    m: usize,
+    modulus: &NS::Num,
+) -> NS {
+    let radix = f.radix();
+    let c = match f.addition() {
+        Addition::Blockwise => a.num_radix(radix).add_mod(y, modulus),
+        Addition::Characterwise => xor::<NS>(a.num_radix(radix), y, m),
+    };
+    NS::str_radix(c, radix, m)
+}
+
+/// Computes C ⊟ y for a half of length m.
+fn sub<F: Feistel, NS: NumeralString>(
+    f: &F,
+    c: &NS,
+    y: NS::Num,
+    m: usize,
+    modulus: &NS::Num,
+) -> NS {
+    let radix = f.radix();
+    let a = match f.addition() {
+        Addition::Blockwise => c.num_radix(radix).sub_mod(y, modulus),
+        Addition::Characterwise => xor::<NS>(c.num_radix(radix), y, m),
+    };
+    NS::str_radix(a, radix, m)
+}
+
+/// Computes the exclusive-or of two integers less than 2^m.
+fn xor<NS: NumeralString>(a: NS::Num, b: NS::Num, m: usize) -> NS::Num {
+    let len = (m + 7) / 8;
+    let a = a.to_bytes(len);
+    let b = b.to_bytes(len);
+    NS::Num::from_bytes(a.as_ref().iter().zip(b.as_ref()).map(|(a, b)| a ^ b))
+}
+
+/// Checks that the Feistel network supports the given numeral string.
+fn check<F: Feistel, NS: NumeralString>(f: &F, x: &NS) -> Result<(), Error> {
+    if f.addition() == Addition::Characterwise && f.radix() != 2 {
+        return Err(Error::InvalidRadix(f.radix()));
+    }
+    check_numerals(x, f.radix())
+}
+
+/// Encrypts the given numeral string with the given Feistel network.
+///
+/// Returns an error if the numeral string is not in the required radix.
+pub fn encrypt<F: Feistel, NS: NumeralString>(f: &F, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+    check(f, x)?;
+    let context = f.context(x.len(), tweak);
+    Ok(encrypt_with(f, &context, &Halves::new(f, x.len()), x))
+}
+
+/// Encrypts the given numeral string, whose length the round function state and the
+/// halves were computed for.
+pub(crate) fn encrypt_with<F: Feistel, NS: NumeralString>(
+    f: &F,
+    context: &F::Context,
+    halves: &Halves<NS::Num>,
+    x: &NS,
+) -> NS {
+    let n = x.len();
+
+    // Let A = X[1..u]; B = X[u + 1..n].
+    let (mut x_a, mut x_b) = x.split(halves.u);
+
+    for i in 0..f.rounds(n) {
+        // If i is even, let m = u; else, let m = v.
+        let (m, modulus) = halves.half(i);
+
+        // Let C = A ⊞ F(n, T, i, B).
+        let y = f.round(context, n, i, &x_b);
+        let x_c = add(f, &x_a, y, m, modulus);
+
+        // Let A = B; B = C.
+        x_a = x_b;
+        x_b = x_c;
+    }
+
+    // Return A || B.
+    NS::concat(x_a, x_b)
+}
+
+/// Decrypts the given numeral string with the given Feistel network.
+///
+/// Returns an error if the numeral string is not in the required radix.
+pub fn decrypt<F: Feistel, NS: NumeralString>(f: &F, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+    check(f, x)?;
+    let context = f.context(x.len(), tweak);
+    Ok(decrypt_with(f, &context, &Halves::new(f, x.len()), x))
+}
+
+/// Decrypts the given numeral string, whose length the round function state and the
+/// halves were computed for.
+pub(crate) fn decrypt_with<F: Feistel, NS: NumeralString>(
+    f: &F,
+    context: &F::Context,
+    halves: &Halves<NS::Num>,
+    x: &NS,
+) -> NS {
+    let n = x.len();
+    let rounds = f.rounds(n);
+
+    // Let A = X[1..l]; B = X[l + 1..n], where l is the length of A after encryption.
+    let l = if rounds % 2 == 0 { halves.u } else { halves.v };
+    let (mut x_a, mut x_b) = x.split(l);
+
+    for i in (0..rounds).rev() {
+        // If i is even, let m = u; else, let m = v.
+        let (m, modulus) = halves.half(i);
+
+        // Let C = B ⊟ F(n, T, i, A).
+        let y = f.round(context, n, i, &x_a);
+        let x_c = sub(f, &x_b, y, m, modulus);
+
+        // Let B = A; A = C.
+        x_b = x_a;
+        x_a = x_c;
+    }
+
+    // Return A || B.
+    NS::concat(x_a, x_b)
+}
+
+/// Checks that the length n is in [min..max].
+fn check_length(n: usize, min: usize, max: usize) -> Result<(), Error> {
+    if n < min || n > max {
+        Err(Error::LengthOutOfBounds { len: n, min, max })
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the given integer as a Numeral.
+fn numeral_from_u64<N: Numeral>(y: u64) -> N {
+    N::from_bytes(y.to_be_bytes().iter().cloned())
+}
+
+/// The state of the FFX A2 and A10 round functions for a given length and tweak.
+pub struct FFXPrefix<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> {
+    /// The CBC-MAC after processing P || T || [0]^((-t-9) mod 16).
+    prf: Prf<CIPH>,
+}
+
+/// Returns the round function state of the A2 and A10 parameter collections, where
+/// P = [vers]^2 || [method]^1 || [addition]^1 || [radix]^1 || [n]^1 || [split(n)]^1 ||
+/// [rnds(n)]^1 || [t]^8.
+fn prefix<F, CIPH>(f: &F, ciph: &CIPH, n: usize, tweak: &[u8]) -> FFXPrefix<CIPH>
+where
+    F: Feistel,
+    CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone,
+{
+    let t = tweak.len();
+    let addition = match f.addition() {
+        Addition::Characterwise => 0,
+        Addition::Blockwise => 1,
+    };
+
+    let mut p = [
+        0,
+        1,
+        2,
+        addition,
+        f.radix() as u8,
+        n as u8,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+    p[6] = f.split(n) as u8;
+    p[7] = f.rounds(n);
+    p[8..16].copy_from_slice(&(t as u64).to_be_bytes());
+
+    let mut prf = Prf::new(ciph);
+    prf.update(&p);
+    prf.update(tweak);
+    for _ in 0..((16 - (t + 9) % 16) % 16) {
+        prf.update(&[0]);
+    }
+    FFXPrefix { prf }
+}
+
+/// Returns Y = CBC-MAC(P || Q) for Q = T || [0]^((-t-9) mod 16) || [i]^1 || [NUM(B)]^8,
+/// as the pair of 64-bit integers (Y[1..64], Y[65..128]).
+fn mac<CIPH, NS>(prefix: &FFXPrefix<CIPH>, radix: u32, i: u8, b: &NS) -> (u64, u64)
+where
+    CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone,
+    NS: NumeralString,
+{
+    let mut prf = prefix.prf.clone();
+    prf.update(&[i]);
+    prf.update(b.num_radix(radix).to_bytes(8).as_ref());
+    let y = prf.output();
+    (
+        u64::from_be_bytes(y[..8].try_into().unwrap()),
+        u64::from_be_bytes(y[8..16].try_into().unwrap()),
+    )
+}
@@ -0,0 +1,224 @@
+//! The FFX A10 parameter collection, for decimal strings.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+
+use super::{check_length, mac, numeral_from_u64, prefix, Addition, FFXPrefix, Feistel};
+use crate::ff1::{new_cipher, NumeralString};
+use crate::Error;
+
+/// A struct for performing FFX-A10 encryption and decryption operations.
+///
+/// FFX-A10 enciphers decimal strings of 4 to 36 digits, using blockwise addition and
+/// between 12 and 24 rounds depending on the length.
+pub struct FFXA10<CIPH: BlockCipher<BlockSize = U16>> {
+    ciph: CIPH,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> FFXA10<CIPH> {
+    /// Creates a new FFX-A10 object for the given key.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires.
+    pub fn new(key: &[u8]) -> Result<Self, Error> {
+        let ciph = new_cipher(key)?;
+        Ok(FFXA10 { ciph })
+    }
+
+    /// Encrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in radix 10, or its length is not
+    /// in [4..36].
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        check_length(x.len(), 4, 36)?;
+        super::encrypt(self, tweak, x)
+    }
+
+    /// Decrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in radix 10, or its length is not
+    /// in [4..36].
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        check_length(x.len(), 4, 36)?;
+        super::decrypt(self, tweak, x)
+    }
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> Feistel for FFXA10<CIPH> {
+    type Context = FFXPrefix<CIPH>;
+
+    fn radix(&self) -> u32 {
+        10
+    }
+
+    fn split(&self, n: usize) -> usize {
+        n / 2
+    }
+
+    fn rounds(&self, n: usize) -> u8 {
+        match n {
+            0..=5 => 24,
+            6..=9 => 18,
+            _ => 12,
+        }
+    }
+
+    fn addition(&self) -> Addition {
+        Addition::Blockwise
+    }
+
+    fn context(&self, n: usize, tweak: &[u8]) -> FFXPrefix<CIPH> {
+        prefix(self, &self.ciph, n, tweak)
+    }
+
+    fn round<NS: NumeralString>(
+        &self,
+        context: &FFXPrefix<CIPH>,
+        n: usize,
+        i: u8,
+        b: &NS,
+    ) -> NS::Num {
+        let (y1, y2) = mac(context, 10, i, b);
+
+        let m = if i % 2 == 0 {
+            self.split(n)
+        } else {
+            n - self.split(n)
+        };
+        let y = if m <= 9 {
+            y2 % 10u64.pow(m as u32)
+        } else {
+            (y1 % 10u64.pow(m as u32 - 9)) * 1_000_000_000 + (y2 % 1_000_000_000)
+        };
+        numeral_from_u64(y)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::collections::BTreeSet;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::FFXA10;
+    use crate::ff1::FlexibleNumeralString;
+
+    #[test]
+    fn test_vectors() {
+        struct TestVector {
+            key: Vec<u8>,
+            tweak: &'static [u8],
+            pt: Vec<u16>,
+            ct: Vec<u16>,
+        }
+
+        let key = vec![
+            0x2B, 0x7E, 0x15, 0x16, 0x28, 0xAE, 0xD2, 0xA6, 0xAB, 0xF7, 0x15, 0x88, 0x09, 0xCF,
+            0x4F, 0x3C,
+        ];
+        let key256 = [
+            &key[..],
+            &[
+                0xEF, 0x43, 0x59, 0xD8, 0xD5, 0x80, 0xAA, 0x4F, 0x7F, 0x03, 0x6D, 0x6F, 0x04, 0xFC,
+                0x6A, 0x94,
+            ],
+        ]
+        .concat();
+
+        // Generated with a separate implementation of the FFX addendum pseudocode, written
+        // in Python on top of the AES of pyca/cryptography.
+        let test_vectors = vec![
+            TestVector {
+                key: key.clone(),
+                tweak: b"",
+                pt: vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+                ct: vec![3, 3, 7, 7, 0, 8, 9, 0, 1, 7],
+            },
+            TestVector {
+                key: key.clone(),
+                tweak: b"9876543210",
+                pt: vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+                ct: vec![4, 2, 8, 3, 1, 4, 7, 2, 3, 7],
+            },
+            TestVector {
+                key: key.clone(),
+                tweak: b"",
+                pt: vec![1, 2, 3, 4],
+                ct: vec![5, 9, 4, 0],
+            },
+            TestVector {
+                key: key.clone(),
+                tweak: &[1, 2, 3],
+                pt: vec![3, 1, 4, 1, 5, 9, 2],
+                ct: vec![5, 7, 5, 3, 6, 5, 5],
+            },
+            TestVector {
+                key: key256,
+                tweak: b"9876543210",
+                pt: vec![
+                    3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9, 3, 2, 3, 8, 4, 6, 2, 6, 4, 3, 3,
+                    8, 3, 2, 7, 9, 5, 0, 2, 8, 8,
+                ],
+                ct: vec![
+                    2, 1, 3, 2, 2, 6, 8, 4, 0, 8, 7, 0, 9, 9, 8, 2, 0, 7, 4, 2, 7, 4, 1, 5, 5, 1,
+                    6, 7, 7, 7, 2, 1, 0, 8, 2, 3,
+                ],
+            },
+        ];
+
+        for tv in test_vectors {
+            let (ct, pt) = if tv.key.len() == 16 {
+                let ff = FFXA10::<Aes128>::new(&tv.key).unwrap();
+                (
+                    ff.encrypt(tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                    ff.decrypt(tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                )
+            } else {
+                let ff = FFXA10::<Aes256>::new(&tv.key).unwrap();
+                (
+                    ff.encrypt(tv.tweak, &FlexibleNumeralString::from(tv.pt.clone())),
+                    ff.decrypt(tv.tweak, &FlexibleNumeralString::from(tv.ct.clone())),
+                )
+            };
+            assert_eq!(Vec::from(ct.unwrap()), tv.ct);
+            assert_eq!(Vec::from(pt.unwrap()), tv.pt);
+        }
+    }
+
+    #[test]
+    fn bijection() {
+        let ff = FFXA10::<Aes128>::new(&[0; 16]).unwrap();
+
+        let mut seen = BTreeSet::new();
+        for x in 0..10_000u16 {
+            let pt = vec![x / 1000, x / 100 % 10, x / 10 % 10, x % 10];
+            let ct = Vec::from(
+                ff.encrypt(&[9], &FlexibleNumeralString::from(pt.clone()))
+                    .unwrap(),
+            );
+            assert!(seen.insert(ct.clone()));
+            let dt = ff.decrypt(&[9], &FlexibleNumeralString::from(ct)).unwrap();
+            assert_eq!(Vec::from(dt), pt);
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        let ff = FFXA10::<Aes128>::new(&[0; 16]).unwrap();
+
+        for n in 4..=36 {
+            let pt: Vec<u16> = (0..n).map(|i| (i * 7 % 10) as u16).collect();
+            let ct = ff
+                .encrypt(&[], &FlexibleNumeralString::from(pt.clone()))
+                .unwrap();
+            let dt = ff.decrypt(&[], &ct).unwrap();
+            assert_eq!(Vec::from(dt), pt);
+        }
+
+        for &n in &[3, 37] {
+            let pt = FlexibleNumeralString::from(vec![0; n]);
+            assert!(ff.encrypt(&[], &pt).is_err());
+            assert!(ff.decrypt(&[], &pt).is_err());
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Format-preserving encryption of integers in an arbitrary range [0..N).
+//!
+//! FF1 permutes the numeral strings of a fixed length, which for radix 2 is the range
+//! [0..2^k). To permute [0..N) for an N that is not a power of two, we pick the
+//! smallest k with N <= 2^k and use cycle walking: the integer is encrypted repeatedly
+//! until the result falls back inside [0..N). Since 2^k < 2N, this takes fewer than two
+//! encryptions on average.
+//!
+//! Under the default strict domain policy, N must be at least 1,000,000 as required by
+//! NIST SP 800-38G Revision 1. Smaller domains must either be created with
+//! `IntegerFF1::with_policy` and `DomainPolicy::Legacy`, or use a small-domain cipher.
+//!
+//! The full domain [0..2^128), whose size does not fit in a `u128`, is created with
+//! `IntegerFF1::full_u128`.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+use crate::ff1::{DomainPolicy, FlexibleNumeralString, FF1, MIN_DOMAIN};
+use crate::Error;
+
+/// A struct for performing format-preserving encryption and decryption of integers in
+/// the range [0..N), using FF1 with cycle walking.
+pub struct IntegerFF1<CIPH: BlockCipher<BlockSize = U16>> {
+    ff: FF1<CIPH>,
+    /// The largest integer of the domain, N - 1.
+    max: u128,
+    bits: usize,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> IntegerFF1<CIPH> {
+    /// Creates a new object for permuting the integers in [0..domain) with the given
+    /// key, under the strict domain policy.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the domain has fewer than 1,000,000 integers.
+    pub fn new(key: &[u8], domain: u128) -> Result<Self, Error> {
+        Self::with_policy(key, domain, DomainPolicy::default())
+    }
+
+    /// Creates a new object for permuting the integers in [0..domain) with the given
+    /// key and domain policy.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, if the domain is empty, or if the domain has fewer than 1,000,000
+    /// integers under the strict domain policy.
+    pub fn with_policy(key: &[u8], domain: u128, policy: DomainPolicy) -> Result<Self, Error> {
+        if domain == 0 {
+            return Err(Error::DomainTooSmall);
+        }
+        Self::with_max(key, domain - 1, policy)
+    }
+
+    /// Creates a new object for permuting all the integers in [0..2^128) with the given
+    /// key.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires.
+    pub fn full_u128(key: &[u8]) -> Result<Self, Error> {
+        Self::with_max(key, u128::max_value(), DomainPolicy::default())
+    }
+
+    fn with_max(key: &[u8], max: u128, policy: DomainPolicy) -> Result<Self, Error> {
+        if policy == DomainPolicy::Strict && max < u128::from(MIN_DOMAIN - 1) {
+            return Err(Error::DomainTooSmall);
+        }
+
+        // The smallest k with N <= 2^k. FF1 requires at least two numerals.
+        let bits = (128 - max.leading_zeros() as usize).max(2);
+
+        let ff = FF1::new(key, 2)?.with_domain_policy(policy);
+        Ok(IntegerFF1 { ff, max, bits })
+    }
+
+    /// Returns the size N of the domain [0..N) that this object permutes, or `None` if
+    /// N = 2^128.
+    pub fn domain(&self) -> Option<u128> {
+        self.max.checked_add(1)
+    }
+
+    /// Encrypts the given integer.
+    ///
+    /// Returns an error if the integer is not in [0..N).
+    pub fn encrypt(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        if x > self.max {
+            return Err(Error::ValueOutOfRange);
+        }
+
+        let mut y = x;
+        loop {
+            y = num_2(self.ff.encrypt(tweak, &str_2(y, self.bits))?);
+            if y <= self.max {
+                return Ok(y);
+            }
+        }
+    }
+
+    /// Decrypts the given integer.
+    ///
+    /// Returns an error if the integer is not in [0..N).
+    pub fn decrypt(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        if x > self.max {
+            return Err(Error::ValueOutOfRange);
+        }
+
+        let mut y = x;
+        loop {
+            y = num_2(self.ff.decrypt(tweak, &str_2(y, self.bits))?);
+            if y <= self.max {
+                return Ok(y);
+            }
+        }
+    }
+
+    /// Encrypts the given `u32`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^32.
+    pub fn encrypt_u32(&self, tweak: &[u8], x: u32) -> Result<u32, Error> {
+        self.encrypt_as(tweak, x)
+    }
+
+    /// Decrypts the given `u32`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^32.
+    pub fn decrypt_u32(&self, tweak: &[u8], x: u32) -> Result<u32, Error> {
+        self.decrypt_as(tweak, x)
+    }
+
+    /// Encrypts the given `u64`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^64.
+    pub fn encrypt_u64(&self, tweak: &[u8], x: u64) -> Result<u64, Error> {
+        self.encrypt_as(tweak, x)
+    }
+
+    /// Decrypts the given `u64`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^64.
+    pub fn decrypt_u64(&self, tweak: &[u8], x: u64) -> Result<u64, Error> {
+        self.decrypt_as(tweak, x)
+    }
+
+    /// Encrypts the given `u128`.
+    ///
+    /// Returns an error if the integer is not in [0..N).
+    pub fn encrypt_u128(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        self.encrypt(tweak, x)
+    }
+
+    /// Decrypts the given `u128`.
+    ///
+    /// Returns an error if the integer is not in [0..N).
+    pub fn decrypt_u128(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        self.decrypt(tweak, x)
+    }
+
+    /// Encrypts the given `i64`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^63.
+    pub fn encrypt_i64(&self, tweak: &[u8], x: i64) -> Result<i64, Error> {
+        self.encrypt_as(tweak, x)
+    }
+
+    /// Decrypts the given `i64`.
+    ///
+    /// Returns an error if the integer is not in [0..N), or if N > 2^63.
+    pub fn decrypt_i64(&self, tweak: &[u8], x: i64) -> Result<i64, Error> {
+        self.decrypt_as(tweak, x)
+    }
+
+    fn encrypt_as<T>(&self, tweak: &[u8], x: T) -> Result<T, Error>
+    where
+        T: TryInto<u128> + TryFrom<u128>,
+    {
+        self.check_fits::<T>()?;
+        let x = x.try_into().map_err(|_| Error::ValueOutOfRange)?;
+        self.encrypt(tweak, x)?
+            .try_into()
+            .map_err(|_| Error::ValueOutOfRange)
+    }
+
+    fn decrypt_as<T>(&self, tweak: &[u8], x: T) -> Result<T, Error>
+    where
+        T: TryInto<u128> + TryFrom<u128>,
+    {
+        self.check_fits::<T>()?;
+        let x = x.try_into().map_err(|_| Error::ValueOutOfRange)?;
+        self.decrypt(tweak, x)?
+            .try_into()
+            .map_err(|_| Error::ValueOutOfRange)
+    }
+
+    /// Checks that every integer in [0..N) can be represented as a `T`, so that the
+    /// output of an encryption or decryption is never out of range.
+    fn check_fits<T: TryFrom<u128>>(&self) -> Result<(), Error> {
+        T::try_from(self.max)
+            .map(|_| ())
+            .map_err(|_| Error::ValueOutOfRange)
+    }
+}
+
+/// Returns the representation of x as a string of m numerals in base 2.
+fn str_2(x: u128, m: usize) -> FlexibleNumeralString {
+    FlexibleNumeralString::from(
+        (0..m)
+            .rev()
+            .map(|i| ((x >> i) & 1) as u16)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Returns the number that the given base-2 numeral string represents.
+fn num_2(x: FlexibleNumeralString) -> u128 {
+    Vec::from(x)
+        .into_iter()
+        .fold(0, |acc, b| (acc << 1) | u128::from(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes256;
+    use alloc::vec;
+
+    use super::IntegerFF1;
+    use crate::ff1::DomainPolicy;
+    use crate::Error;
+
+    #[test]
+    fn bijection() {
+        for &domain in &[1, 2, 3, 5, 7, 8, 9, 100, 1000, 1025] {
+            let ff =
+                IntegerFF1::<Aes256>::with_policy(&[0; 32], domain, DomainPolicy::Legacy).unwrap();
+
+            let mut seen = vec![false; domain as usize];
+            for x in 0..domain {
+                let ct = ff.encrypt(&[], x).unwrap();
+                assert!(ct < domain);
+                assert!(!seen[ct as usize]);
+                seen[ct as usize] = true;
+                assert_eq!(ff.decrypt(&[], ct).unwrap(), x);
+            }
+            assert!(seen.into_iter().all(|b| b));
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(IntegerFF1::<Aes256>::new(&[0; 32], 0).is_err());
+        assert!(IntegerFF1::<Aes256>::with_policy(&[0; 32], 0, DomainPolicy::Legacy).is_err());
+
+        // The strict domain policy requires N >= 1,000,000, even though 2^20 integers
+        // would be enough for FF1.
+        for &domain in &[1 << 19, (1 << 19) + 1, 999_999] {
+            assert_eq!(
+                IntegerFF1::<Aes256>::new(&[0; 32], domain).err(),
+                Some(Error::DomainTooSmall)
+            );
+        }
+        let ff = IntegerFF1::<Aes256>::new(&[0; 32], 1_000_000).unwrap();
+        assert!(ff.encrypt(&[], 0).is_ok());
+        let ff =
+            IntegerFF1::<Aes256>::with_policy(&[0; 32], 999_999, DomainPolicy::Legacy).unwrap();
+        assert!(ff.encrypt(&[], 0).is_ok());
+
+        let ff = IntegerFF1::<Aes256>::new(&[0; 32], 7_340_211).unwrap();
+        assert!(ff.encrypt(&[], 7_340_211).is_err());
+        assert!(ff.decrypt(&[], 7_340_211).is_err());
+        assert!(ff.encrypt_i64(&[], -1).is_err());
+
+        let x = ff.encrypt_u32(&[], 1_234_567).unwrap();
+        assert_eq!(u128::from(x), ff.encrypt(&[], 1_234_567).unwrap());
+        assert_eq!(ff.decrypt_u32(&[], x).unwrap(), 1_234_567);
+
+        let x = ff.encrypt_i64(&[], 1_234_567).unwrap();
+        assert_eq!(ff.decrypt_i64(&[], x).unwrap(), 1_234_567);
+
+        // Outputs could exceed u32::MAX for this domain.
+        let ff = IntegerFF1::<Aes256>::new(&[0; 32], 1 << 40).unwrap();
+        assert!(ff.encrypt_u32(&[], 5).is_err());
+        let x = ff.encrypt_u64(&[], 5).unwrap();
+        assert_eq!(ff.decrypt_u64(&[], x).unwrap(), 5);
+    }
+
+    #[test]
+    fn full_width() {
+        let ff = IntegerFF1::<Aes256>::new(&[0; 32], u128::max_value()).unwrap();
+        let x = u128::max_value() - 1;
+        let ct = ff.encrypt_u128(&[1, 2, 3], x).unwrap();
+        assert_eq!(ff.decrypt_u128(&[1, 2, 3], ct).unwrap(), x);
+        assert_eq!(ff.domain(), Some(u128::max_value()));
+
+        let ff = IntegerFF1::<Aes256>::full_u128(&[0; 32]).unwrap();
+        assert_eq!(ff.domain(), None);
+        for &x in &[0, 1, u128::max_value() - 1, u128::max_value()] {
+            let ct = ff.encrypt_u128(&[1, 2, 3], x).unwrap();
+            assert_eq!(ff.decrypt_u128(&[1, 2, 3], ct).unwrap(), x);
+        }
+        assert_ne!(
+            ff.encrypt_u128(&[], u128::max_value()).unwrap(),
+            u128::max_value()
+        );
+        assert!(ff.encrypt_u64(&[], 5).is_err());
+    }
+}
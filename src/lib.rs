@@ -6,6 +6,7 @@
 //! extern crate aes;
 //! extern crate fpe;
 //!
+//! # #[cfg(feature = "alloc")] {
 //! use aes::Aes256;
 //! use fpe::ff1::{BinaryNumeralString, FF1};
 //!
@@ -19,8 +20,48 @@
 //!
 //! let p2 = ff.decrypt(&[], &ct).unwrap();
 //! assert_eq!(p2.to_bytes_le(), pt);
+//! # }
 //! ```
+//!
+//! # Features
+//!
+//! This crate is `no_std`. FF1, FF3-1 and the FFX engine work without an allocator,
+//! using `ff1::ArrayNumeralString`. The `alloc` feature adds the heap-allocated
+//! numeral strings, together with the Naor-Reingold, integer and wide-block modules. The `std`
+//! feature, which is enabled by default, implies `alloc` and adds the FAST and
+//! small-domain modules, which use floating-point arithmetic.
+//!
+//! The `parallel` feature adds `FF1::par_encrypt` and `FF1::par_decrypt`, which encrypt
+//! many numeral strings on the rayon thread pool.
+//!
+//! The `constant-time` feature adds `ff1::ConstantTimeNumeralString`, which encrypts
+//! with arithmetic whose timing does not depend on the numerals.
 
+#![no_std]
 #![deny(missing_docs)] // refuse to compile if documentation is missing
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod error;
+pub use error::Error;
+
+#[cfg(feature = "std")]
+pub mod fast;
 pub mod ff1;
+#[cfg(feature = "legacy-ff3")]
+pub mod ff3;
+pub mod ff3_1;
+pub mod ffx;
+#[cfg(feature = "alloc")]
+pub mod integer;
+#[cfg(feature = "alloc")]
+pub mod naor_reingold;
+#[cfg(feature = "regex")]
+pub mod regex;
+#[cfg(feature = "std")]
+pub mod small_domain;
+#[cfg(feature = "alloc")]
+pub mod wide;
@@ -0,0 +1,426 @@
+//! A small-block cipher for binary strings of up to 128 bits, using the construction
+//! of Naor and Reingold.
+//!
+//! It is meant for short binary values such as IPv4 addresses, ports and MAC addresses.
+//! Following Naor and Reingold, it sandwiches a Feistel network between a
+//! pairwise-independent permutation and its inverse. The pairwise-independent
+//! permutation is x -> A·x ⊕ b over GF(2)^n, for an invertible matrix A and a vector b
+//! derived from the key. This implementation uses seven Feistel rounds, with the block
+//! cipher as round function.
+//!
+//! The design is close to FNR (Flexible Naor and Reingold) by
+//! [Dara and Fluhrer](https://eprint.iacr.org/2014/421), but this is not FNR: the
+//! derivation of A and b from the key, and the encoding of the tweak and of the round
+//! function inputs, are specific to this crate. Ciphertexts are not compatible with
+//! libfnr or with other FNR implementations.
+//!
+//! All arithmetic is done on native integers, so encryption does not allocate.
+
+use aes::block_cipher::{generic_array::typenum::U16, Block, BlockCipher, NewBlockCipher};
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+use crate::ff1::{new_cipher, Prf};
+use crate::Error;
+
+/// The number of Feistel rounds.
+const ROUNDS: u8 = 7;
+
+/// Returns the mask for the low n bits.
+fn mask(n: u32) -> u128 {
+    if n == 128 {
+        !0
+    } else {
+        (1 << n) - 1
+    }
+}
+
+/// Returns A·x over GF(2), where row j of A determines bit j of the result.
+fn mul(rows: &[u128], x: u128) -> u128 {
+    rows.iter().enumerate().fold(0, |acc, (j, row)| {
+        acc | (u128::from((row & x).count_ones() & 1) << j)
+    })
+}
+
+/// Returns the inverse of A over GF(2), or None if A is singular.
+fn invert(rows: &[u128]) -> Option<Vec<u128>> {
+    let n = rows.len();
+    let mut a = rows.to_vec();
+    let mut inv: Vec<u128> = (0..n).map(|j| 1 << j).collect();
+
+    // Gauss-Jordan elimination on [A | I].
+    for col in 0..n {
+        let pivot = (col..n).find(|&r| (a[r] >> col) & 1 == 1)?;
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        for r in 0..n {
+            if r != col && (a[r] >> col) & 1 == 1 {
+                a[r] ^= a[col];
+                inv[r] ^= inv[col];
+            }
+        }
+    }
+    Some(inv)
+}
+
+/// A struct for performing Naor-Reingold encryption and decryption operations.
+pub struct NaorReingold<CIPH: BlockCipher<BlockSize = U16>> {
+    ciph: CIPH,
+    bits: u32,
+    /// The rows of the matrix A of the pairwise-independent permutation.
+    matrix: Vec<u128>,
+    /// The rows of A^-1.
+    inverse: Vec<u128>,
+    /// The vector b of the pairwise-independent permutation.
+    vector: u128,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> NaorReingold<CIPH> {
+    /// Creates a new Naor-Reingold object for the given key and block size in bits.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the block size is not in [2..128].
+    pub fn new(key: &[u8], bits: u32) -> Result<Self, Error> {
+        if !(2..=128).contains(&bits) {
+            return Err(Error::LengthOutOfBounds {
+                len: bits as usize,
+                min: 2,
+                max: 128,
+            });
+        }
+        let ciph: CIPH = new_cipher(key)?;
+
+        // Derive the pairwise-independent permutation from the block cipher in counter
+        // mode, sampling matrices until we find an invertible one.
+        let mut counter = 0u64;
+        let mut next = || {
+            let mut block = Block::<CIPH>::default();
+            block[..4].copy_from_slice(b"FNR\x01");
+            block[4] = bits as u8;
+            block[8..].copy_from_slice(&counter.to_be_bytes());
+            ciph.encrypt_block(&mut block);
+            counter += 1;
+            u128::from_be_bytes(block.as_slice().try_into().unwrap()) & mask(bits)
+        };
+
+        let vector = next();
+        let (matrix, inverse) = loop {
+            let matrix: Vec<u128> = (0..bits).map(|_| next()).collect();
+            if let Some(inverse) = invert(&matrix) {
+                break (matrix, inverse);
+            }
+        };
+
+        Ok(NaorReingold {
+            ciph,
+            bits,
+            matrix,
+            inverse,
+            vector,
+        })
+    }
+
+    /// Returns the block size in bits.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns D = PRF(P || T || [0]^((-t) mod 16)), from which the round function
+    /// inputs are derived.
+    fn tweak(&self, tweak: &[u8]) -> Block<CIPH> {
+        // P = ["FNR"] || [0] || [n] || [0]^3 || [t]^8
+        let mut p = [0; 16];
+        p[..3].copy_from_slice(b"FNR");
+        p[4] = self.bits as u8;
+        p[8..].copy_from_slice(&(tweak.len() as u64).to_be_bytes());
+
+        let mut prf = Prf::new(&self.ciph);
+        prf.update(&p);
+        prf.update(tweak);
+        for _ in 0..((16 - tweak.len() % 16) % 16) {
+            prf.update(&[0]);
+        }
+        *prf.output()
+    }
+
+    /// Returns the round function output F_i(B), truncated to m bits.
+    fn round(&self, d: &Block<CIPH>, i: u8, b: u128, m: u32) -> u128 {
+        // Y = CIPH(D ⊕ ([i] || [0]^7 || [B]^8)), continuing the CBC-MAC of the tweak.
+        let mut block = *d;
+        block[0] ^= i;
+        for (y, b) in block[8..].iter_mut().zip((b as u64).to_be_bytes().iter()) {
+            *y ^= b;
+        }
+        self.ciph.encrypt_block(&mut block);
+
+        let y = u64::from_be_bytes(block[..8].try_into().unwrap());
+        u128::from(y >> (64 - m))
+    }
+
+    /// Encrypts the given integer.
+    ///
+    /// Returns an error if x >= 2^n.
+    pub fn encrypt(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        if x & !mask(self.bits) != 0 {
+            return Err(Error::ValueOutOfRange);
+        }
+        let d = self.tweak(tweak);
+
+        let u = self.bits / 2;
+        let v = self.bits - u;
+
+        // Apply the pairwise-independent permutation.
+        let x = mul(&self.matrix, x) ^ self.vector;
+
+        // Let A be the high u bits, and B the low v bits.
+        let mut x_a = x >> v;
+        let mut x_b = x & mask(v);
+
+        for i in 0..ROUNDS {
+            let m = if i % 2 == 0 { u } else { v };
+
+            // Let C = A ⊕ F_i(B); A = B; B = C.
+            let x_c = x_a ^ self.round(&d, i, x_b, m);
+            x_a = x_b;
+            x_b = x_c;
+        }
+
+        // After an odd number of rounds, A has v bits and B has u bits.
+        let y = (x_a << u) | x_b;
+
+        // Apply the inverse of the pairwise-independent permutation.
+        Ok(mul(&self.inverse, y ^ self.vector))
+    }
+
+    /// Decrypts the given integer.
+    ///
+    /// Returns an error if x >= 2^n.
+    pub fn decrypt(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        if x & !mask(self.bits) != 0 {
+            return Err(Error::ValueOutOfRange);
+        }
+        let d = self.tweak(tweak);
+
+        let u = self.bits / 2;
+        let v = self.bits - u;
+
+        // Undo the inverse of the pairwise-independent permutation.
+        let y = mul(&self.matrix, x) ^ self.vector;
+
+        // After an odd number of rounds, A has v bits and B has u bits.
+        let mut x_a = y >> u;
+        let mut x_b = y & mask(u);
+
+        for i in (0..ROUNDS).rev() {
+            let m = if i % 2 == 0 { u } else { v };
+
+            // Let C = B ⊕ F_i(A); B = A; A = C.
+            let x_c = x_b ^ self.round(&d, i, x_a, m);
+            x_b = x_a;
+            x_a = x_c;
+        }
+
+        let x = (x_a << v) | x_b;
+
+        // Undo the pairwise-independent permutation.
+        Ok(mul(&self.inverse, x ^ self.vector))
+    }
+
+    /// Encrypts the given `u32`.
+    ///
+    /// Returns an error if x >= 2^n, or if n > 32.
+    pub fn encrypt_u32(&self, tweak: &[u8], x: u32) -> Result<u32, Error> {
+        self.encrypt_as(tweak, x)
+    }
+
+    /// Decrypts the given `u32`.
+    ///
+    /// Returns an error if x >= 2^n, or if n > 32.
+    pub fn decrypt_u32(&self, tweak: &[u8], x: u32) -> Result<u32, Error> {
+        self.decrypt_as(tweak, x)
+    }
+
+    /// Encrypts the given `u64`.
+    ///
+    /// Returns an error if x >= 2^n, or if n > 64.
+    pub fn encrypt_u64(&self, tweak: &[u8], x: u64) -> Result<u64, Error> {
+        self.encrypt_as(tweak, x)
+    }
+
+    /// Decrypts the given `u64`.
+    ///
+    /// Returns an error if x >= 2^n, or if n > 64.
+    pub fn decrypt_u64(&self, tweak: &[u8], x: u64) -> Result<u64, Error> {
+        self.decrypt_as(tweak, x)
+    }
+
+    /// Encrypts the given `u128`.
+    ///
+    /// Returns an error if x >= 2^n.
+    pub fn encrypt_u128(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        self.encrypt(tweak, x)
+    }
+
+    /// Decrypts the given `u128`.
+    ///
+    /// Returns an error if x >= 2^n.
+    pub fn decrypt_u128(&self, tweak: &[u8], x: u128) -> Result<u128, Error> {
+        self.decrypt(tweak, x)
+    }
+
+    /// Encrypts the given block in place, as a big-endian integer of ceil(n / 8) bytes.
+    ///
+    /// Returns an error if the block has the wrong length, or represents an integer
+    /// x >= 2^n.
+    pub fn encrypt_bytes(&self, tweak: &[u8], block: &mut [u8]) -> Result<(), Error> {
+        let x = self.load(block)?;
+        self.store(self.encrypt(tweak, x)?, block);
+        Ok(())
+    }
+
+    /// Decrypts the given block in place, as a big-endian integer of ceil(n / 8) bytes.
+    ///
+    /// Returns an error if the block has the wrong length, or represents an integer
+    /// x >= 2^n.
+    pub fn decrypt_bytes(&self, tweak: &[u8], block: &mut [u8]) -> Result<(), Error> {
+        let x = self.load(block)?;
+        self.store(self.decrypt(tweak, x)?, block);
+        Ok(())
+    }
+
+    fn encrypt_as<T>(&self, tweak: &[u8], x: T) -> Result<T, Error>
+    where
+        T: Into<u128> + TryFrom<u128>,
+    {
+        T::try_from(mask(self.bits)).map_err(|_| Error::ValueOutOfRange)?;
+        self.encrypt(tweak, x.into())?
+            .try_into()
+            .map_err(|_| Error::ValueOutOfRange)
+    }
+
+    fn decrypt_as<T>(&self, tweak: &[u8], x: T) -> Result<T, Error>
+    where
+        T: Into<u128> + TryFrom<u128>,
+    {
+        T::try_from(mask(self.bits)).map_err(|_| Error::ValueOutOfRange)?;
+        self.decrypt(tweak, x.into())?
+            .try_into()
+            .map_err(|_| Error::ValueOutOfRange)
+    }
+
+    fn load(&self, block: &[u8]) -> Result<u128, Error> {
+        let len = ((self.bits + 7) / 8) as usize;
+        if block.len() != len {
+            return Err(Error::LengthOutOfBounds {
+                len: block.len(),
+                min: len,
+                max: len,
+            });
+        }
+        Ok(block.iter().fold(0, |acc, b| (acc << 8) | u128::from(*b)))
+    }
+
+    fn store(&self, x: u128, block: &mut [u8]) {
+        let bytes = x.to_be_bytes();
+        block.copy_from_slice(&bytes[16 - block.len()..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::collections::BTreeSet;
+
+    use super::{invert, mul, NaorReingold};
+
+    #[test]
+    fn pwip() {
+        let ff = NaorReingold::<Aes128>::new(&[0; 16], 128).unwrap();
+        assert_eq!(invert(&ff.inverse).unwrap(), ff.matrix);
+        for &x in &[0, 1, 0xdead_beef, !0] {
+            assert_eq!(mul(&ff.inverse, mul(&ff.matrix, x)), x);
+        }
+
+        // A singular matrix
+        assert!(invert(&[0b01, 0b01]).is_none());
+    }
+
+    #[test]
+    fn bijection() {
+        for &bits in &[2, 3, 8, 11] {
+            let ff = NaorReingold::<Aes128>::new(&[1; 16], bits).unwrap();
+
+            let mut seen = BTreeSet::new();
+            for x in 0..(1u128 << bits) {
+                let ct = ff.encrypt(&[1, 2, 3], x).unwrap();
+                assert!(ct < 1 << bits);
+                assert!(seen.insert(ct));
+                assert_eq!(ff.decrypt(&[1, 2, 3], ct).unwrap(), x);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let ff = NaorReingold::<Aes256>::new(&[2; 32], 32).unwrap();
+        let ip = u32::from_be_bytes([192, 168, 1, 1]);
+        let ct = ff.encrypt_u32(&[], ip).unwrap();
+        assert_ne!(ct, ip);
+        assert_ne!(ff.encrypt_u32(&[0], ip).unwrap(), ct);
+        assert_eq!(ff.decrypt_u32(&[], ct).unwrap(), ip);
+
+        let mut block = [192, 168, 1, 1];
+        ff.encrypt_bytes(&[], &mut block).unwrap();
+        assert_eq!(u32::from_be_bytes(block), ct);
+        ff.decrypt_bytes(&[], &mut block).unwrap();
+        assert_eq!(block, [192, 168, 1, 1]);
+
+        let ff = NaorReingold::<Aes256>::new(&[2; 32], 48).unwrap();
+        let mut mac = [0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6];
+        ff.encrypt_bytes(b"mac", &mut mac).unwrap();
+        ff.decrypt_bytes(b"mac", &mut mac).unwrap();
+        assert_eq!(mac, [0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6]);
+        let x = ff.encrypt_u64(&[], 0xffff_ffff_ffff).unwrap();
+        assert_eq!(ff.decrypt_u64(&[], x).unwrap(), 0xffff_ffff_ffff);
+
+        let ff = NaorReingold::<Aes256>::new(&[2; 32], 128).unwrap();
+        let x = ff.encrypt_u128(&[], !0).unwrap();
+        assert_eq!(ff.decrypt_u128(&[], x).unwrap(), !0);
+    }
+
+    #[test]
+    fn test_vectors() {
+        // Regression vectors for this crate's construction, which is not FNR and does not
+        // interoperate with libfnr.
+        let ff = NaorReingold::<Aes128>::new(&[0x2B; 16], 32).unwrap();
+        assert_eq!(ff.encrypt(&[], 0xc0a8_0101).unwrap(), 0xdf94_c18f);
+        assert_eq!(ff.decrypt(&[], 0xdf94_c18f).unwrap(), 0xc0a8_0101);
+
+        let ff = NaorReingold::<Aes256>::new(&[7; 32], 48).unwrap();
+        assert_eq!(
+            ff.encrypt(b"mac", 0x001b_6384_45e6).unwrap(),
+            0x3ebd_ef26_230d
+        );
+
+        let ff = NaorReingold::<Aes128>::new(&[0; 16], 128).unwrap();
+        assert_eq!(
+            ff.encrypt(&[1, 2, 3], 0).unwrap(),
+            0x479b_d730_5f13_ad1a_101a_cfcf_b276_b0fd
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(NaorReingold::<Aes128>::new(&[0; 16], 1).is_err());
+        assert!(NaorReingold::<Aes128>::new(&[0; 16], 129).is_err());
+
+        let ff = NaorReingold::<Aes128>::new(&[0; 16], 16).unwrap();
+        assert!(ff.encrypt(&[], 1 << 16).is_err());
+        assert!(ff.encrypt_bytes(&[], &mut [0; 3]).is_err());
+
+        let ff = NaorReingold::<Aes128>::new(&[0; 16], 40).unwrap();
+        // Outputs could exceed u32::MAX for this block size.
+        assert!(ff.encrypt_u32(&[], 0).is_err());
+        assert!(ff.encrypt_u64(&[], 0).is_ok());
+    }
+}
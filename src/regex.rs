@@ -0,0 +1,380 @@
+//! Format-preserving encryption for formats described by regular expressions.
+//!
+//! This implements the rank-encipher-unrank construction of
+//! [Bellare et al.](https://eprint.iacr.org/2009/251) and
+//! [Dyer et al.](https://eprint.iacr.org/2012/494). The regular expression is compiled
+//! into a DFA, which is used to count the strings of a given length in the language.
+//! A string is encrypted by mapping it to its index (rank) in the lexicographically
+//! ordered list of those strings, encrypting the rank with FF1 and cycle walking, and
+//! mapping the result back to a string (unranking). The ciphertext is therefore
+//! always in the same language, and has the same length, as the plaintext.
+//!
+//! Lengths are measured in bytes of the UTF-8 encoding. Under the default strict
+//! domain policy, there must be at least 1,000,000 matching strings of the given
+//! length, as required by NIST SP 800-38G Revision 1. This module is gated behind the
+//! `regex` feature.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use num_bigint::BigUint;
+use num_traits::identities::{One, Zero};
+use regex_automata::{dense, DFA};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::ff1::{DomainPolicy, FlexibleNumeralString, NumeralString, FF1, MIN_DOMAIN};
+use crate::Error;
+
+/// A DFA over bytes, with its states renumbered densely from zero.
+struct Automaton {
+    start: usize,
+    /// The transitions out of each state, indexed by input byte.
+    transitions: Vec<Vec<usize>>,
+    /// The distinct targets out of each state, with the number of bytes leading to each.
+    edges: Vec<Vec<(usize, u32)>>,
+    accepting: Vec<bool>,
+}
+
+impl Automaton {
+    /// Compiles the given pattern into an automaton that accepts exactly the strings
+    /// that the pattern matches in full.
+    fn new(pattern: &str) -> Result<Self, Error> {
+        let dfa = dense::Builder::new()
+            .anchored(true)
+            .longest_match(true)
+            .minimize(true)
+            .build(pattern)
+            .map_err(|_| Error::InvalidPattern)?;
+
+        // Renumber the reachable states from zero.
+        let mut ids = HashMap::new();
+        let mut queue = vec![dfa.start_state()];
+        ids.insert(dfa.start_state(), 0);
+        let mut transitions = vec![];
+        let mut accepting = vec![];
+        while let Some(&id) = queue.get(transitions.len()) {
+            let mut next = Vec::with_capacity(256);
+            for b in 0..=255 {
+                let target = dfa.next_state(id, b);
+                let index = match ids.get(&target) {
+                    Some(&index) => index,
+                    None => {
+                        let index = queue.len();
+                        ids.insert(target, index);
+                        queue.push(target);
+                        index
+                    }
+                };
+                next.push(index);
+            }
+            transitions.push(next);
+            accepting.push(dfa.is_match_state(id));
+        }
+
+        let edges = transitions
+            .iter()
+            .map(|next| {
+                let mut counts: Vec<(usize, u32)> = vec![];
+                for &target in next {
+                    match counts.iter_mut().find(|(t, _)| *t == target) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((target, 1)),
+                    }
+                }
+                counts
+            })
+            .collect();
+
+        Ok(Automaton {
+            start: 0,
+            transitions,
+            edges,
+            accepting,
+        })
+    }
+
+    /// Extends the table T, such that T[i][q] is the number of strings of length i that
+    /// are accepted starting from state q, to every i in [0..n].
+    ///
+    /// Each row costs one big-integer multiply-add per distinct transition.
+    fn extend_counts(&self, table: &mut Vec<Vec<BigUint>>, n: usize) {
+        if table.is_empty() {
+            table.push(
+                self.accepting
+                    .iter()
+                    .map(|&a| if a { BigUint::one() } else { BigUint::zero() })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        while table.len() <= n {
+            let prev: &Vec<BigUint> = &table[table.len() - 1];
+            let row = self
+                .edges
+                .iter()
+                .map(|edges| {
+                    edges
+                        .iter()
+                        .fold(BigUint::zero(), |acc, &(q, c)| acc + &prev[q] * c)
+                })
+                .collect();
+            table.push(row);
+        }
+    }
+
+    /// Returns the index of x among the accepted strings of length x.len(), in
+    /// lexicographic byte order.
+    ///
+    /// Returns an error if x is not accepted.
+    fn rank(&self, table: &[Vec<BigUint>], x: &[u8]) -> Result<BigUint, Error> {
+        let n = x.len();
+        let mut q = self.start;
+        let mut rank = BigUint::zero();
+        for (j, &c) in x.iter().enumerate() {
+            for b in 0..c {
+                rank += &table[n - j - 1][self.transitions[q][b as usize]];
+            }
+            q = self.transitions[q][c as usize];
+        }
+        if self.accepting[q] {
+            Ok(rank)
+        } else {
+            Err(Error::NoMatch)
+        }
+    }
+
+    /// Returns the accepted string of length n with the given index, in lexicographic
+    /// byte order.
+    ///
+    /// The caller MUST ensure that rank < T[n][start].
+    fn unrank(&self, table: &[Vec<BigUint>], mut rank: BigUint, n: usize) -> Vec<u8> {
+        let mut q = self.start;
+        let mut res = Vec::with_capacity(n);
+        for j in 0..n {
+            for b in 0..=255 {
+                let next = self.transitions[q][b as usize];
+                let count = &table[n - j - 1][next];
+                if rank < *count {
+                    res.push(b);
+                    q = next;
+                    break;
+                }
+                rank -= count;
+            }
+        }
+        assert!(self.accepting[q]);
+        res
+    }
+}
+
+/// A struct for performing format-preserving encryption and decryption of strings
+/// that match a regular expression, using FF1 with cycle walking.
+pub struct RegexFF1<CIPH: BlockCipher<BlockSize = U16>> {
+    ff: FF1<CIPH>,
+    automaton: Automaton,
+    /// The table of counts, up to the longest length used so far.
+    counts: Mutex<Arc<Vec<Vec<BigUint>>>>,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> RegexFF1<CIPH> {
+    /// Creates a new object for the given key and regular expression.
+    ///
+    /// The regular expression always matches the whole string; it does not need to be
+    /// anchored with `^` and `$`, and anchors are not supported.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the regular expression cannot be compiled.
+    pub fn new(key: &[u8], pattern: &str) -> Result<Self, Error> {
+        let ff = FF1::new(key, 2)?;
+        let automaton = Automaton::new(pattern)?;
+        Ok(RegexFF1 {
+            ff,
+            automaton,
+            counts: Mutex::new(Arc::new(vec![])),
+        })
+    }
+
+    /// Sets the domain policy of the underlying FF1 instance.
+    pub fn with_domain_policy(mut self, policy: DomainPolicy) -> Self {
+        self.ff = self.ff.with_domain_policy(policy);
+        self
+    }
+
+    /// Returns the number of strings of length n (in bytes) that match the regular
+    /// expression.
+    pub fn count(&self, n: usize) -> BigUint {
+        self.counts(n)[n][self.automaton.start].clone()
+    }
+
+    /// Returns the table of counts for every length in [0..n].
+    ///
+    /// The table takes O(n * states) big-integer operations to compute, so it is cached
+    /// and only extended when a longer length is used. Its memory is proportional to
+    /// the longest length used so far.
+    fn counts(&self, n: usize) -> Arc<Vec<Vec<BigUint>>> {
+        // The table is only extended by whole rows, so it is still valid if another
+        // thread panicked while holding the lock.
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        if counts.len() <= n {
+            self.automaton.extend_counts(Arc::make_mut(&mut counts), n);
+        }
+        Arc::clone(&counts)
+    }
+
+    /// Encrypts the given string.
+    ///
+    /// Returns an error if the string does not match the regular expression, or if
+    /// there are too few matching strings of its length for the domain policy.
+    pub fn encrypt(&self, tweak: &[u8], x: &str) -> Result<String, Error> {
+        self.walk(x, |y| self.ff.encrypt(tweak, y))
+    }
+
+    /// Decrypts the given string.
+    ///
+    /// Returns an error if the string does not match the regular expression, or if
+    /// there are too few matching strings of its length for the domain policy.
+    pub fn decrypt(&self, tweak: &[u8], x: &str) -> Result<String, Error> {
+        self.walk(x, |y| self.ff.decrypt(tweak, y))
+    }
+
+    /// Ranks x, applies f to the rank with cycle walking, and unranks the result.
+    fn walk<F>(&self, x: &str, f: F) -> Result<String, Error>
+    where
+        F: Fn(&FlexibleNumeralString) -> Result<FlexibleNumeralString, Error>,
+    {
+        let n = x.len();
+        let table = self.counts(n);
+        let rank = self.automaton.rank(&table, x.as_bytes())?;
+
+        let domain = &table[n][self.automaton.start];
+        if self.ff.domain_policy() == DomainPolicy::Strict && *domain < MIN_DOMAIN.into() {
+            return Err(Error::DomainTooSmall);
+        }
+
+        // Permute [0..domain) by walking the cycles of FF1 over [0..2^bits) until we
+        // land back inside the domain. FF1 requires at least two numerals.
+        let bits = ((domain - 1u32).bits() as usize).max(2);
+        let mut y = rank;
+        loop {
+            y = f(&FlexibleNumeralString::str_radix(y.into(), 2, bits))?
+                .num_radix(2)
+                .into();
+            if y < *domain {
+                break;
+            }
+        }
+
+        // The automaton only accepts valid UTF-8.
+        String::from_utf8(self.automaton.unrank(&table, y, n)).map_err(|_| Error::NoMatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes256;
+    use alloc::format;
+    use alloc::vec;
+    use num_bigint::BigUint;
+    use std::collections::HashSet;
+
+    use super::RegexFF1;
+    use crate::ff1::DomainPolicy;
+    use crate::Error;
+
+    #[test]
+    fn count() {
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "[A-Z]{2}[0-9]{2} ?[A-Z0-9]{3,4}").unwrap();
+        let letters = BigUint::from(26u32 * 26);
+        let digits = BigUint::from(100u32);
+        let alnum3 = BigUint::from(36u32.pow(3));
+        let alnum4 = BigUint::from(36u32.pow(4));
+
+        assert_eq!(ff.count(6), BigUint::from(0u32));
+        assert_eq!(ff.count(7), &letters * &digits * &alnum3);
+        assert_eq!(
+            ff.count(8),
+            &letters * &digits * &alnum3 + &letters * &digits * &alnum4
+        );
+        assert_eq!(ff.count(9), &letters * &digits * &alnum4);
+        assert_eq!(ff.count(10), BigUint::from(0u32));
+        // Shorter lengths are read from the cached table.
+        assert_eq!(ff.count(7), &letters * &digits * &alnum3);
+
+        // Leftmost-first semantics must not hide the longer alternative.
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "a|ab").unwrap();
+        assert_eq!(ff.count(1), BigUint::from(1u32));
+        assert_eq!(ff.count(2), BigUint::from(1u32));
+    }
+
+    #[test]
+    fn round_trip() {
+        let pattern = "[A-Z]{2}[0-9]{2} ?[A-Z0-9]{3,4}";
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], pattern).unwrap();
+        let re = RegexFF1::<Aes256>::new(&[1; 32], pattern).unwrap();
+
+        for pt in &["AB12CD3", "AB12 CD3", "AB12CD34", "AB12 CD34"] {
+            let ct = ff.encrypt(&[], pt).unwrap();
+            assert_eq!(ct.len(), pt.len());
+            // The ciphertext is in the language.
+            assert!(re.encrypt(&[], &ct).is_ok());
+            assert_eq!(&ff.decrypt(&[], &ct).unwrap(), pt);
+        }
+
+        assert!(ff.encrypt(&[], "ab12cd3").is_err());
+        assert!(ff.encrypt(&[], "").is_err());
+    }
+
+    #[test]
+    fn bijection() {
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "[a-c]x?[0-4]")
+            .unwrap()
+            .with_domain_policy(DomainPolicy::Legacy);
+
+        for (n, expected) in [(2, 15), (3, 15)].iter().cloned() {
+            let mut seen = HashSet::new();
+            let mut strings = vec![];
+            for a in "abc".chars() {
+                for d in "01234".chars() {
+                    strings.push(if n == 2 {
+                        format!("{}{}", a, d)
+                    } else {
+                        format!("{}x{}", a, d)
+                    });
+                }
+            }
+            for pt in &strings {
+                let ct = ff.encrypt(&[], pt).unwrap();
+                assert!(strings.contains(&ct));
+                assert!(seen.insert(ct.clone()));
+                assert_eq!(&ff.decrypt(&[], &ct).unwrap(), pt);
+            }
+            assert_eq!(seen.len(), expected);
+        }
+    }
+
+    #[test]
+    fn min_domain() {
+        // 800,000 strings, which FF1 could encrypt as 20-bit ranks, but which is below
+        // the strict minimum domain size.
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "[0-9]{5}[0-7]").unwrap();
+        assert_eq!(ff.encrypt(&[], "123456"), Err(Error::DomainTooSmall));
+        assert_eq!(ff.decrypt(&[], "123456"), Err(Error::DomainTooSmall));
+
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "[0-9]{6}").unwrap();
+        let ct = ff.encrypt(&[], "123456").unwrap();
+        assert_eq!(ff.decrypt(&[], &ct).unwrap(), "123456");
+    }
+
+    #[test]
+    fn unicode() {
+        let ff = RegexFF1::<Aes256>::new(&[0; 32], "[αβγ]{4}").unwrap();
+        assert_eq!(ff.encrypt(&[], "αββγ"), Err(Error::DomainTooSmall));
+
+        let ff = ff.with_domain_policy(DomainPolicy::Legacy);
+        let ct = ff.encrypt(&[], "αββγ").unwrap();
+        assert_eq!(ct.chars().count(), 4);
+        assert!(ct.chars().all(|c| "αβγ".contains(c)));
+        assert_eq!(ff.decrypt(&[], &ct).unwrap(), "αββγ");
+    }
+}
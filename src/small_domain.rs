@@ -0,0 +1,304 @@
+//! A small-domain cipher for numeral strings whose domain is too small for FF1.
+//!
+//! NIST Special Publication 800-38G Revision 1 requires radix^minlen >= 1,000,000 for
+//! FF1 and FF3-1, as Feistel-based ciphers are not secure on smaller domains. This
+//! module instead implements the
+//! [Sometimes-Recurse shuffle](https://eprint.iacr.org/2013/560) of Morris and
+//! Rogaway, using [Swap-or-Not](https://eprint.iacr.org/2012/475) (Hoang, Morris and
+//! Rogaway) as its inner shuffle. It is fully secure, even when an adversary can
+//! query the whole domain, but it is expensive.
+//!
+//! For a domain of size N, stage j runs Swap-or-Not on n_j = floor(N / 2^j) values
+//! with ceil(7.23 * lg(n_j) + 4.82 * 128 + 4.82 * lg(lg(N))) rounds, which is over
+//! 620 rounds. Each round makes two block cipher calls, one for the round key and one
+//! for the round function. A value only moves on to the next stage when it lands in
+//! the lower half of the current one, so an encryption runs two stages on average, and
+//! up to lg(N) stages. For N = 10,000, that is about 1,460 block cipher calls per
+//! stage, about 2,900 per encryption on average, and up to about 19,000.
+//!
+//! The PRF underlying the shuffle is the same CBC-MAC construction that FF1 uses.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use alloc::vec;
+use core::convert::TryInto;
+
+use crate::ff1::{check_numerals, new_cipher, Numeral, NumeralString, Prf, Radix};
+use crate::Error;
+
+/// The security parameter used to choose the number of Swap-or-Not rounds.
+const SECURITY_BITS: f64 = 128.0;
+
+/// A tweaked instance of the Sometimes-Recurse shuffle on [0..N).
+struct Shuffle<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16>> {
+    prf: Prf<CIPH>,
+    domain: u64,
+    stages: u32,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> Shuffle<CIPH> {
+    fn new(ciph: &CIPH, domain: u64, tweak: &[u8]) -> Self {
+        // P = ["SRSN"] || [N]^8 || [t]^4, followed by the tweak padded to a multiple of
+        // the block length.
+        let mut p = [b'S', b'R', b'S', b'N', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        p[4..12].copy_from_slice(&domain.to_be_bytes());
+        p[12..16].copy_from_slice(&(tweak.len() as u32).to_be_bytes());
+
+        let mut prf = Prf::new(ciph);
+        prf.update(&p);
+        prf.update(tweak);
+        for _ in 0..((16 - tweak.len() % 16) % 16) {
+            prf.update(&[0]);
+        }
+
+        // Stage j permutes [0..floor(N / 2^j)], down to a domain of size 1.
+        let stages = 64 - domain.leading_zeros();
+
+        Shuffle {
+            prf,
+            domain,
+            stages,
+        }
+    }
+
+    /// Returns the number of Swap-or-Not rounds for a stage on [0..n), following the
+    /// bound given by Morris and Rogaway for Swap-or-Not as the inner shuffle.
+    fn rounds(&self, n: u64) -> u16 {
+        let lg_n = (n as f64).log2();
+        let lg_stages = f64::from(self.stages).log2();
+        (7.23 * lg_n + 4.82 * SECURITY_BITS + 4.82 * lg_stages).ceil() as u16
+    }
+
+    /// Returns the round key K_i in [0..n) for round i of stage j.
+    fn round_key(&self, j: u32, i: u16, n: u64) -> u64 {
+        let mut q = [0; 16];
+        q[1] = j as u8;
+        q[2..4].copy_from_slice(&i.to_be_bytes());
+
+        let mut prf = self.prf.clone();
+        prf.update(&q);
+        let r = u128::from_be_bytes(prf.output().as_slice().try_into().unwrap());
+        (r % u128::from(n)) as u64
+    }
+
+    /// Returns the round function bit F_i(X̂) for round i of stage j.
+    fn round_bit(&self, j: u32, i: u16, x: u64) -> bool {
+        let mut q = [0; 16];
+        q[0] = 1;
+        q[1] = j as u8;
+        q[2..4].copy_from_slice(&i.to_be_bytes());
+        q[4..12].copy_from_slice(&x.to_be_bytes());
+
+        let mut prf = self.prf.clone();
+        prf.update(&q);
+        prf.output()[15] & 1 != 0
+    }
+
+    /// Applies round i of the Swap-or-Not shuffle on [0..n) for stage j.
+    ///
+    /// Each round is an involution, so this is used for both directions.
+    fn swap_or_not(&self, j: u32, i: u16, n: u64, x: u64) -> u64 {
+        // X' = (K_i - X) mod n
+        let k = self.round_key(j, i, n);
+        let x_prime = ((u128::from(k) + u128::from(n) - u128::from(x)) % u128::from(n)) as u64;
+
+        // X̂ = max(X, X'); if F_i(X̂) = 1 then X = X'
+        if self.round_bit(j, i, x.max(x_prime)) {
+            x_prime
+        } else {
+            x
+        }
+    }
+
+    fn encrypt(&self, mut x: u64) -> u64 {
+        let mut n = self.domain;
+        for j in 0..self.stages {
+            if n == 1 {
+                break;
+            }
+
+            for i in 0..self.rounds(n) {
+                x = self.swap_or_not(j, i, n, x);
+            }
+
+            // Values in the lower half recurse on a domain of half the size.
+            if x >= n / 2 {
+                break;
+            }
+            n /= 2;
+        }
+        x
+    }
+
+    fn decrypt(&self, mut y: u64) -> u64 {
+        // Find the last stage that the plaintext went through, which is the stage j for
+        // which y lies in the upper half [floor(n_j / 2)..n_j).
+        let mut sizes = vec![];
+        let mut n = self.domain;
+        while n > 1 {
+            sizes.push(n);
+            if y >= n / 2 {
+                break;
+            }
+            n /= 2;
+        }
+
+        for (j, &n) in sizes.iter().enumerate().rev() {
+            for i in (0..self.rounds(n)).rev() {
+                y = self.swap_or_not(j as u32, i, n, y);
+            }
+        }
+        y
+    }
+}
+
+/// A struct for performing small-domain encryption and decryption operations.
+pub struct SmallDomain<CIPH: BlockCipher<BlockSize = U16>> {
+    ciph: CIPH,
+    radix: Radix,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> SmallDomain<CIPH> {
+    /// Creates a new small-domain cipher object for the given key and radix.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, or if the given radix is not in [2..2^16].
+    pub fn new(key: &[u8], radix: u32) -> Result<Self, Error> {
+        let ciph = new_cipher(key)?;
+        let radix = Radix::from(radix)?;
+        Ok(SmallDomain { ciph, radix })
+    }
+
+    /// Encrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or if
+    /// radix^n is not less than 2^64.
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        let (domain, num) = self.num_radix(x)?;
+        let y = Shuffle::new(&self.ciph, domain, tweak).encrypt(num);
+        Ok(self.str_radix(y, x.len()))
+    }
+
+    /// Decrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or if
+    /// radix^n is not less than 2^64.
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        let (domain, num) = self.num_radix(x)?;
+        let y = Shuffle::new(&self.ciph, domain, tweak).decrypt(num);
+        Ok(self.str_radix(y, x.len()))
+    }
+
+    /// Returns radix^n and NUM_radix(X).
+    fn num_radix<NS: NumeralString>(&self, x: &NS) -> Result<(u64, u64), Error> {
+        let radix = self.radix.to_u32();
+        check_numerals(x, radix)?;
+
+        let mut domain = 1u64;
+        for len in 0..x.len() {
+            domain = domain
+                .checked_mul(u64::from(radix))
+                .ok_or(Error::LengthOutOfBounds {
+                    len: x.len(),
+                    min: 0,
+                    max: len,
+                })?;
+        }
+
+        let bytes = x.num_radix(radix).to_bytes(8);
+        Ok((
+            domain,
+            u64::from_be_bytes(bytes.as_ref().try_into().unwrap()),
+        ))
+    }
+
+    /// Returns STR^m_radix(x).
+    fn str_radix<NS: NumeralString>(&self, x: u64, m: usize) -> NS {
+        let x = NS::Num::from_bytes(x.to_be_bytes().iter().cloned());
+        NS::str_radix(x, self.radix.to_u32(), m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::{Aes128, Aes256};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::collections::HashSet;
+
+    use super::SmallDomain;
+    use crate::ff1::{BinaryNumeralString, FlexibleNumeralString};
+
+    #[test]
+    fn bijection() {
+        for &(radix, n) in &[(10, 1), (10, 2), (26, 1), (3, 3)] {
+            let sd = SmallDomain::<Aes128>::new(&[0; 16], radix).unwrap();
+
+            let mut seen = HashSet::new();
+            let mut pt = vec![0; n];
+            loop {
+                let ct = sd
+                    .encrypt(&[1, 2, 3], &FlexibleNumeralString::from(pt.clone()))
+                    .unwrap();
+                let ct = Vec::from(ct);
+                assert!(ct.iter().all(|&d| u32::from(d) < radix));
+                assert!(seen.insert(ct.clone()));
+
+                let dt = sd
+                    .decrypt(&[1, 2, 3], &FlexibleNumeralString::from(ct))
+                    .unwrap();
+                assert_eq!(Vec::from(dt), pt);
+
+                // Increment pt as a base-radix counter.
+                match pt.iter().rposition(|&d| u32::from(d) + 1 < radix) {
+                    Some(i) => {
+                        pt[i] += 1;
+                        for d in &mut pt[i + 1..] {
+                            *d = 0;
+                        }
+                    }
+                    None => break,
+                }
+            }
+            assert_eq!(seen.len() as u32, radix.pow(n as u32));
+        }
+    }
+
+    #[test]
+    fn tweak() {
+        let sd = SmallDomain::<Aes256>::new(&[7; 32], 10).unwrap();
+        let pt = FlexibleNumeralString::from(vec![1, 2, 3]);
+
+        let ct: Vec<_> = (0u8..8)
+            .map(|t| Vec::from(sd.encrypt(&[t], &pt).unwrap()))
+            .collect();
+        assert!(ct.iter().any(|c| *c != ct[0]));
+
+        for (t, c) in ct.into_iter().enumerate() {
+            let dt = sd
+                .decrypt(&[t as u8], &FlexibleNumeralString::from(c))
+                .unwrap();
+            assert_eq!(Vec::from(dt), vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn binary() {
+        let sd = SmallDomain::<Aes256>::new(&[0; 32], 2).unwrap();
+        let pt = BinaryNumeralString::from_bytes_le(&[0xab]);
+        let ct = sd.encrypt(&[], &pt).unwrap();
+        let dt = sd.decrypt(&[], &ct).unwrap();
+        assert_eq!(dt.to_bytes_le(), vec![0xab]);
+    }
+
+    #[test]
+    fn invalid() {
+        let sd = SmallDomain::<Aes128>::new(&[0; 16], 10).unwrap();
+        assert!(sd
+            .encrypt(&[], &FlexibleNumeralString::from(vec![10]))
+            .is_err());
+        // 10^20 > 2^64
+        assert!(sd
+            .encrypt(&[], &FlexibleNumeralString::from(vec![0; 20]))
+            .is_err());
+    }
+}
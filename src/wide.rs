@@ -0,0 +1,270 @@
+//! Format-preserving encryption of long numeral strings.
+//!
+//! FF1 treats each half of a numeral string as a single integer, so its cost grows
+//! quadratically with the length of the string. This module instead splits the string
+//! into chunks of bounded length and encrypts each chunk with FF1, in two passes:
+//!
+//! - a forward pass, where the tweak of chunk i includes the output of chunk i - 1;
+//! - a backward pass, where the tweak of chunk i includes the output of chunk i + 1.
+//!
+//! After the forward pass, the last chunk depends on every input numeral, and the
+//! backward pass carries that dependency to every other chunk. Each chunk is encrypted
+//! twice, so the cost is linear in the length of the string.
+//!
+//! Chunks are never shorter than the minimum length of the FF1 domain policy, so they
+//! may be longer than the configured chunk length when it is less than twice that
+//! minimum.
+
+use aes::block_cipher::{generic_array::typenum::U16, BlockCipher, NewBlockCipher};
+use alloc::vec::Vec;
+
+use crate::ff1::{check_numerals, DomainPolicy, Numeral, NumeralString, Radix, FF1};
+use crate::Error;
+
+/// A struct for performing format-preserving encryption and decryption of long
+/// numeral strings, by chaining FF1 over chunks of bounded length.
+pub struct WideFF1<CIPH: BlockCipher<BlockSize = U16>> {
+    ff: FF1<CIPH>,
+    radix: Radix,
+    chunk_len: usize,
+}
+
+impl<CIPH: NewBlockCipher + BlockCipher<BlockSize = U16> + Clone> WideFF1<CIPH> {
+    /// Creates a new object for the given key and radix, which encrypts chunks of at
+    /// most `chunk_len` numerals at a time.
+    ///
+    /// Returns an error if the key does not have the length that the block cipher
+    /// requires, if the given radix is not in [2..2^16], or if `chunk_len` is less
+    /// than 2.
+    pub fn new(key: &[u8], radix: u32, chunk_len: usize) -> Result<Self, Error> {
+        if chunk_len < 2 {
+            return Err(Error::LengthOutOfBounds {
+                len: chunk_len,
+                min: 2,
+                max: usize::max_value(),
+            });
+        }
+        let ff = FF1::new(key, radix)?;
+        let radix = Radix::from(radix)?;
+        Ok(WideFF1 {
+            ff,
+            radix,
+            chunk_len,
+        })
+    }
+
+    /// Sets the domain policy of the underlying FF1 instance.
+    pub fn with_domain_policy(mut self, policy: DomainPolicy) -> Self {
+        self.ff = self.ff.with_domain_policy(policy);
+        self
+    }
+
+    /// Returns the maximum number of numerals in a chunk.
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    /// Encrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or if it
+    /// is shorter than the domain policy allows.
+    pub fn encrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        let n = x.len();
+        let mut chunks = self.chunks(x)?;
+        let k = chunks.len();
+
+        // Forward pass: Y_i = FF1.Encrypt(K, T_i, X_i), with T_i depending on Y_{i-1}.
+        for i in 0..k {
+            let t = self.chunk_tweak(tweak, 0, n, i, i.checked_sub(1).map(|j| &chunks[j]));
+            chunks[i] = self.ff.encrypt(&t, &chunks[i])?;
+        }
+
+        // Backward pass: Z_i = FF1.Encrypt(K, T'_i, Y_i), with T'_i depending on Z_{i+1}.
+        for i in (0..k).rev() {
+            let t = self.chunk_tweak(tweak, 1, n, i, chunks.get(i + 1));
+            chunks[i] = self.ff.encrypt(&t, &chunks[i])?;
+        }
+
+        Ok(join(chunks))
+    }
+
+    /// Decrypts the given numeral string.
+    ///
+    /// Returns an error if the numeral string is not in the required radix, or if it
+    /// is shorter than the domain policy allows.
+    pub fn decrypt<NS: NumeralString>(&self, tweak: &[u8], x: &NS) -> Result<NS, Error> {
+        let n = x.len();
+        let mut chunks = self.chunks(x)?;
+        let k = chunks.len();
+
+        // Undo the backward pass, in forward order.
+        for i in 0..k {
+            let t = self.chunk_tweak(tweak, 1, n, i, chunks.get(i + 1));
+            chunks[i] = self.ff.decrypt(&t, &chunks[i])?;
+        }
+
+        // Undo the forward pass, in backward order.
+        for i in (0..k).rev() {
+            let t = self.chunk_tweak(tweak, 0, n, i, i.checked_sub(1).map(|j| &chunks[j]));
+            chunks[i] = self.ff.decrypt(&t, &chunks[i])?;
+        }
+
+        Ok(join(chunks))
+    }
+
+    /// Splits the given numeral string into ceil(n / chunk_len) chunks, whose lengths
+    /// differ by at most one, or fewer chunks if they would be shorter than FF1 allows.
+    fn chunks<NS: NumeralString>(&self, x: &NS) -> Result<Vec<NS>, Error> {
+        check_numerals(x, self.radix.to_u32())?;
+
+        // A string that is too short is rejected by FF1 as a single chunk.
+        let n = x.len();
+        let k = (n / self.chunk_len + usize::from(n % self.chunk_len != 0))
+            .min(n / self.ff.min_len())
+            .max(1);
+        let sizes: Vec<usize> = (0..k).map(|i| n / k + usize::from(i < n % k)).collect();
+
+        let mut chunks = Vec::with_capacity(k);
+        split(x, &sizes, &mut chunks);
+        Ok(chunks)
+    }
+
+    /// Returns the tweak for chunk i in the given pass, where
+    /// T_i = [pass]^1 || [i]^8 || [n]^8 || [t]^8 || T || [NUM_radix(C)]^b, and C is the
+    /// neighbouring chunk that the tweak depends on, if any.
+    ///
+    /// The lengths are encoded on eight bytes so that they are never truncated, and
+    /// distinct (n, i) pairs always have distinct tweaks.
+    fn chunk_tweak<NS: NumeralString>(
+        &self,
+        tweak: &[u8],
+        pass: u8,
+        n: usize,
+        i: usize,
+        chain: Option<&NS>,
+    ) -> Vec<u8> {
+        let mut t = Vec::with_capacity(25 + tweak.len());
+        t.push(pass);
+        t.extend_from_slice(&(i as u64).to_be_bytes());
+        t.extend_from_slice(&(n as u64).to_be_bytes());
+        t.extend_from_slice(&(tweak.len() as u64).to_be_bytes());
+        t.extend_from_slice(tweak);
+        if let Some(c) = chain {
+            let b = self.radix.calculate_b(c.len());
+            t.extend_from_slice(c.num_radix(self.radix.to_u32()).to_bytes(b).as_ref());
+        }
+        t
+    }
+}
+
+/// Splits x into chunks of the given sizes, appending them to `out`.
+///
+/// This recurses on halves, so that each numeral is copied O(log k) times.
+fn split<NS: NumeralString>(x: &NS, sizes: &[usize], out: &mut Vec<NS>) {
+    if sizes.len() == 1 {
+        out.push(x.split(x.len()).0);
+        return;
+    }
+    let mid = sizes.len() / 2;
+    let (a, b) = x.split(sizes[..mid].iter().sum());
+    split(&a, &sizes[..mid], out);
+    split(&b, &sizes[mid..], out);
+}
+
+/// Concatenates the given chunks.
+fn join<NS: NumeralString>(chunks: Vec<NS>) -> NS {
+    let mut chunks = chunks.into_iter();
+    let first = chunks.next().unwrap();
+    chunks.fold(first, NS::concat)
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::Aes256;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::WideFF1;
+    use crate::ff1::{DomainPolicy, FlexibleNumeralString};
+    use crate::Error;
+
+    #[test]
+    fn round_trip() {
+        let ff = WideFF1::<Aes256>::new(&[0; 32], 10, 16).unwrap();
+
+        for &n in &[6, 11, 16, 17, 33, 1000] {
+            let pt: Vec<u16> = (0..n).map(|i| (i % 10) as u16).collect();
+            let ct = ff
+                .encrypt(b"notes", &FlexibleNumeralString::from(pt.clone()))
+                .unwrap();
+            let ct = Vec::from(ct);
+            assert_eq!(ct.len(), n);
+            assert!(ct.iter().all(|&d| d < 10));
+
+            let dt = ff
+                .decrypt(b"notes", &FlexibleNumeralString::from(ct))
+                .unwrap();
+            assert_eq!(Vec::from(dt), pt);
+        }
+    }
+
+    #[test]
+    fn diffusion() {
+        let ff = WideFF1::<Aes256>::new(&[0; 32], 36, 8).unwrap();
+        let pt = vec![0; 100];
+        let ct = Vec::from(
+            ff.encrypt(&[], &FlexibleNumeralString::from(pt.clone()))
+                .unwrap(),
+        );
+
+        // Changing the last numeral changes the first chunk, and vice versa.
+        let mut last = pt.clone();
+        last[99] = 1;
+        let ct_last = Vec::from(ff.encrypt(&[], &FlexibleNumeralString::from(last)).unwrap());
+        assert_ne!(ct_last[..8], ct[..8]);
+
+        let mut first = pt;
+        first[0] = 1;
+        let ct_first = Vec::from(
+            ff.encrypt(&[], &FlexibleNumeralString::from(first))
+                .unwrap(),
+        );
+        assert_ne!(ct_first[92..], ct[92..]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(WideFF1::<Aes256>::new(&[0; 32], 10, 1).is_err());
+        assert!(WideFF1::<Aes256>::new(&[0; 32], 1, 16).is_err());
+
+        let ff = WideFF1::<Aes256>::new(&[0; 32], 10, 16).unwrap();
+        assert_eq!(
+            ff.encrypt(&[], &FlexibleNumeralString::from(vec![0; 5]))
+                .err(),
+            Some(Error::DomainTooSmall)
+        );
+
+        let ff = ff.with_domain_policy(DomainPolicy::Legacy);
+        let ct = ff
+            .encrypt(&[], &FlexibleNumeralString::from(vec![1, 2]))
+            .unwrap();
+        assert_eq!(Vec::from(ff.decrypt(&[], &ct).unwrap()), vec![1, 2]);
+        assert!(ff
+            .encrypt(&[], &FlexibleNumeralString::from(vec![0]))
+            .is_err());
+        let mut x = vec![0; 20];
+        x.push(10);
+        assert!(ff.encrypt(&[], &FlexibleNumeralString::from(x)).is_err());
+    }
+
+    #[test]
+    fn unbounded_chunks() {
+        // A single chunk, without overflowing when counting the chunks.
+        let ff = WideFF1::<Aes256>::new(&[0; 32], 10, usize::max_value()).unwrap();
+        let pt: Vec<u16> = (0..40).map(|i| i % 10).collect();
+        let ct = ff
+            .encrypt(&[], &FlexibleNumeralString::from(pt.clone()))
+            .unwrap();
+        assert_eq!(Vec::from(ff.decrypt(&[], &ct).unwrap()), pt);
+    }
+}
@@ -0,0 +1,59 @@
+//! Checks that FF1 encryption in place does not allocate.
+//!
+//! This is an integration test so that its global allocator only counts the
+//! allocations of this test binary, which runs a single test. Only allocations on the
+//! test's own thread are counted, as the test harness may allocate concurrently.
+
+use aes::Aes256;
+use fpe::ff1::FF1;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = Cell::new(false);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn in_place_does_not_allocate() {
+    let ff = FF1::<Aes256>::new(&[0; 32], 10).unwrap();
+    let ff2 = FF1::<Aes256>::new(&[0; 32], 2).unwrap();
+    let mut numerals = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+    let mut bytes = [0xab; 24];
+
+    COUNTING.with(|c| c.set(true));
+    for _ in 0..100 {
+        ff.encrypt_in_place(b"tweak", &mut numerals).unwrap();
+        ff.decrypt_in_place(b"tweak", &mut numerals).unwrap();
+        ff2.encrypt_bytes_in_place(&[], &mut bytes).unwrap();
+        ff2.decrypt_bytes_in_place(&[], &mut bytes).unwrap();
+    }
+    COUNTING.with(|c| c.set(false));
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), 0);
+
+    assert_eq!(
+        numerals,
+        [1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]
+    );
+    assert_eq!(bytes, [0xab; 24]);
+}